@@ -0,0 +1,427 @@
+use std::ops::{Index, IndexMut, Range};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+// Git gives up on finding the shortest diff after this many edits, or the square root of the size if that's more
+const MAX_COST_MIN: usize = 256;
+
+// Histogram diff falls back to Myers when the rarest line it can find shows up more than this often
+const MAX_CHAIN: usize = 64;
+
+/// How to line up the lines of the two files
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Algorithm {
+    /// Myers, giving up on the shortest diff for very different files like git does
+    #[default]
+    Myers,
+    /// Myers, always finding the shortest diff however long it takes
+    Minimal,
+    /// Line up the lines that only appear once on each side first
+    Patience,
+    /// Line up the rarest lines first, like patience but better with repeated lines
+    Histogram,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 4] = [Algorithm::Myers, Algorithm::Minimal, Algorithm::Patience, Algorithm::Histogram];
+
+    pub fn next(self) -> Self {
+        Self::ALL[(Self::ALL.iter().position(|&a| a == self).unwrap_or(0) + 1) % Self::ALL.len()]
+    }
+
+    pub fn differ(self) -> &'static dyn Differ {
+        match self {
+            Algorithm::Myers => &Myers { minimal: false },
+            Algorithm::Minimal => &Myers { minimal: true },
+            Algorithm::Patience => &Patience,
+            Algorithm::Histogram => &Histogram,
+        }
+    }
+}
+
+/// What happens to one line on the way from the left file to the right one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// A diff algorithm. Lines come in as ids, where equal lines have equal ids
+pub trait Differ: Sync {
+    /// The ops that turn `a` into `b`, in order
+    fn diff(&self, a: &[usize], b: &[usize]) -> Vec<Op> {
+        let mut ops = Vec::with_capacity(a.len() + b.len());
+        self.diff_range(a, 0..a.len(), b, 0..b.len(), &mut ops);
+        ops
+    }
+
+    fn diff_range(&self, a: &[usize], ar: Range<usize>, b: &[usize], br: Range<usize>, ops: &mut Vec<Op>);
+}
+
+struct Myers {
+    minimal: bool,
+}
+
+impl Differ for Myers {
+    fn diff_range(&self, a: &[usize], ar: Range<usize>, b: &[usize], br: Range<usize>, ops: &mut Vec<Op>) {
+        let max = ar.len() + br.len() + 1;
+        let mut vf = V::new(max);
+        let mut vb = V::new(max);
+        let max_cost = match self.minimal {
+            true => usize::MAX,
+            false => std::cmp::max(MAX_COST_MIN, max.isqrt()),
+        };
+
+        conquer(a, ar, b, br, &mut vf, &mut vb, max_cost, ops);
+    }
+}
+
+/// The furthest x reached on each diagonal k = x - y
+struct V {
+    offset: isize,
+    v: Vec<usize>,
+}
+
+impl V {
+    fn new(max: usize) -> Self {
+        Self { offset: max as isize + 1, v: vec![0; 2 * max + 3] }
+    }
+}
+
+impl Index<isize> for V {
+    type Output = usize;
+
+    fn index(&self, k: isize) -> &usize {
+        &self.v[(k + self.offset) as usize]
+    }
+}
+
+impl IndexMut<isize> for V {
+    fn index_mut(&mut self, k: isize) -> &mut usize {
+        &mut self.v[(k + self.offset) as usize]
+    }
+}
+
+/// Split the ranges at the middle of the shortest edit script and do each half
+#[allow(clippy::too_many_arguments)]
+fn conquer(a: &[usize], ar: Range<usize>, b: &[usize], br: Range<usize>, vf: &mut V, vb: &mut V, max_cost: usize, ops: &mut Vec<Op>) {
+    let (ar, br, suffix) = trim(a, ar, b, br, ops);
+
+    if ar.is_empty() || br.is_empty() {
+        ops.extend(std::iter::repeat_n(Op::Delete, ar.len()));
+        ops.extend(std::iter::repeat_n(Op::Insert, br.len()));
+    } else {
+        let (x, y) = middle_snake(a, ar.clone(), b, br.clone(), vf, vb, max_cost);
+        if (x, y) == (ar.start, br.start) || (x, y) == (ar.end, br.end) {
+            // A split that doesn't split anything would go around forever
+            ops.extend(std::iter::repeat_n(Op::Delete, ar.len()));
+            ops.extend(std::iter::repeat_n(Op::Insert, br.len()));
+        } else {
+            conquer(a, ar.start..x, b, br.start..y, vf, vb, max_cost, ops);
+            conquer(a, x..ar.end, b, y..br.end, vf, vb, max_cost, ops);
+        }
+    }
+
+    ops.extend(std::iter::repeat_n(Op::Equal, suffix));
+}
+
+/// Where to split two ranges that have no common prefix or suffix. Searches forwards and backwards
+/// at once until the paths meet, or until `max_cost` edits in, at which point the forward path that
+/// got furthest will do
+fn middle_snake(a: &[usize], ar: Range<usize>, b: &[usize], br: Range<usize>, vf: &mut V, vb: &mut V, max_cost: usize) -> (usize, usize) {
+    let (n, m) = (ar.len(), br.len());
+    let delta = n as isize - m as isize;
+    let odd = delta & 1 == 1;
+    vf[1] = 0;
+    vb[1] = 0;
+
+    let d_max = (n + m).div_ceil(2) as isize + 1;
+    for d in 0..d_max {
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && vf[k - 1] < vf[k + 1]) { vf[k + 1] } else { vf[k - 1] + 1 };
+            let y = (x as isize - k) as usize;
+            let start = (x, y);
+            if x < n && y < m {
+                x += common_prefix(a, ar.start + x..ar.end, b, br.start + y..br.end);
+            }
+            vf[k] = x;
+
+            if odd && (k - delta).abs() < d && vf[k] + vb[delta - k] >= n {
+                return (ar.start + start.0, br.start + start.1);
+            }
+        }
+
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && vb[k - 1] < vb[k + 1]) { vb[k + 1] } else { vb[k - 1] + 1 };
+            let mut y = (x as isize - k) as usize;
+            if x < n && y < m {
+                let common = common_suffix(a, ar.start..ar.end - x, b, br.start..br.end - y);
+                x += common;
+                y += common;
+            }
+            vb[k] = x;
+
+            if !odd && (k - delta).abs() <= d && vb[k] + vf[delta - k] >= n {
+                return (ar.end - x, br.end - y);
+            }
+        }
+
+        // Too expensive to find the best split, so take the forward path that's furthest along
+        if d as usize >= max_cost {
+            let (k, x) = (-d..=d).step_by(2)
+                .map(|k| (k, vf[k]))
+                .filter(|&(k, x)| x <= n && x as isize - k >= 0 && (x as isize - k) as usize <= m)
+                .max_by_key(|&(k, x)| 2 * x as isize - k)
+                .unwrap_or((0, 0));
+            return (ar.start + x, br.start + (x as isize - k) as usize);
+        }
+    }
+
+    // The paths always meet by now
+    (ar.end, br.end)
+}
+
+struct Patience;
+
+impl Differ for Patience {
+    fn diff_range(&self, a: &[usize], ar: Range<usize>, b: &[usize], br: Range<usize>, ops: &mut Vec<Op>) {
+        let (ar, br, suffix) = trim(a, ar, b, br, ops);
+
+        // Lines found exactly once on each side, kept if they don't cross each other
+        let anchors = longest_increasing(&unique_pairs(a, ar.clone(), b, br.clone()));
+        if anchors.is_empty() {
+            Myers { minimal: false }.diff_range(a, ar, b, br, ops);
+        } else {
+            let (mut i, mut j) = (ar.start, br.start);
+            for (x, y) in anchors {
+                self.diff_range(a, i..x, b, j..y, ops);
+                ops.push(Op::Equal);
+                (i, j) = (x + 1, y + 1);
+            }
+            self.diff_range(a, i..ar.end, b, j..br.end, ops);
+        }
+
+        ops.extend(std::iter::repeat_n(Op::Equal, suffix));
+    }
+}
+
+/// Pairs of positions of the lines that show up once in each range, in order on the left
+fn unique_pairs(a: &[usize], ar: Range<usize>, b: &[usize], br: Range<usize>) -> Vec<(usize, usize)> {
+    // Per id: how often it's on the left, where, and where on the right (or MAX if more than once)
+    let mut seen: std::collections::HashMap<usize, (usize, usize, Option<usize>)> = std::collections::HashMap::new();
+    for i in ar {
+        let e = seen.entry(a[i]).or_insert((0, i, None));
+        e.0 += 1;
+    }
+    for j in br {
+        if let Some(e) = seen.get_mut(&b[j]) {
+            e.2 = if e.2.is_none() { Some(j) } else { Some(usize::MAX) };
+        }
+    }
+
+    let mut pairs = seen.into_values()
+        .filter_map(|(count, i, j)| j.filter(|&j| count == 1 && j != usize::MAX).map(|j| (i, j)))
+        .collect::<Vec<(usize, usize)>>();
+    pairs.sort_unstable();
+    pairs
+}
+
+/// Patience sort the pairs by their right index to keep the longest run that doesn't cross
+pub fn longest_increasing(pairs: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut tails: Vec<usize> = Vec::new();
+    let mut prev: Vec<Option<usize>> = vec![None; pairs.len()];
+
+    for (k, &(_, j)) in pairs.iter().enumerate() {
+        let pos = tails.partition_point(|&t| pairs[t].1 < j);
+        prev[k] = pos.checked_sub(1).map(|p| tails[p]);
+        if pos == tails.len() {
+            tails.push(k);
+        } else {
+            tails[pos] = k;
+        }
+    }
+
+    let mut result = Vec::with_capacity(tails.len());
+    let mut k = tails.last().copied();
+    while let Some(i) = k {
+        result.push(pairs[i]);
+        k = prev[i];
+    }
+    result.reverse();
+    result
+}
+
+struct Histogram;
+
+impl Differ for Histogram {
+    fn diff_range(&self, a: &[usize], ar: Range<usize>, b: &[usize], br: Range<usize>, ops: &mut Vec<Op>) {
+        // Loop over what's after each match instead of recursing, so a long run of small matches can't
+        // blow the stack. Every suffix trimmed off is inside the last one, so they all go at the end
+        let (mut ar, mut br, mut suffix) = trim(a, ar, b, br, ops);
+
+        loop {
+            if ar.is_empty() || br.is_empty() {
+                ops.extend(std::iter::repeat_n(Op::Delete, ar.len()));
+                ops.extend(std::iter::repeat_n(Op::Insert, br.len()));
+                break;
+            }
+
+            let Some((x, y, len)) = rarest_match(a, ar.clone(), b, br.clone()) else {
+                Myers { minimal: false }.diff_range(a, ar, b, br, ops);
+                break;
+            };
+
+            self.diff_range(a, ar.start..x, b, br.start..y, ops);
+            ops.extend(std::iter::repeat_n(Op::Equal, len));
+
+            let rest = trim(a, x + len..ar.end, b, y + len..br.end, ops);
+            (ar, br) = (rest.0, rest.1);
+            suffix += rest.2;
+        }
+
+        ops.extend(std::iter::repeat_n(Op::Equal, suffix));
+    }
+}
+
+/// The run of matching lines built around the line that's rarest on the left, longest first among
+/// equally rare ones. Gives up if every line is too common
+fn rarest_match(a: &[usize], ar: Range<usize>, b: &[usize], br: Range<usize>) -> Option<(usize, usize, usize)> {
+    let mut positions: std::collections::HashMap<usize, Vec<usize>> = std::collections::HashMap::new();
+    for i in ar.clone() {
+        positions.entry(a[i]).or_default().push(i);
+    }
+
+    // (how often the rarest line in the run shows up on the left, start on the left, start on the right, length)
+    let mut best: Option<(usize, usize, usize, usize)> = None;
+    let mut j = br.start;
+    while j < br.end {
+        let Some(starts) = positions.get(&b[j]).filter(|p| p.len() <= MAX_CHAIN) else {
+            j += 1;
+            continue;
+        };
+
+        let mut next = j + 1;
+        for &i in starts {
+            if best.is_some_and(|(count, ..)| count < starts.len()) {
+                break;
+            }
+
+            // Grow the match both ways, keeping track of the rarest line in it
+            let (mut x, mut y, mut end) = (i, j, 0);
+            let mut count = starts.len();
+            while x > ar.start && y > br.start && a[x - 1] == b[y - 1] {
+                x -= 1;
+                y -= 1;
+                count = std::cmp::min(count, positions[&a[x]].len());
+            }
+            while i + end < ar.end && j + end < br.end && a[i + end] == b[j + end] {
+                count = std::cmp::min(count, positions[&a[i + end]].len());
+                end += 1;
+            }
+            let len = i + end - x;
+
+            next = std::cmp::max(next, j + end);
+            if best.is_none_or(|(c, _, _, l)| count < c || (count == c && len > l)) {
+                best = Some((count, x, y, len));
+            }
+        }
+        j = next;
+    }
+
+    best.map(|(_, x, y, len)| (x, y, len))
+}
+
+/// Push the common prefix and shrink the ranges past it and the common suffix. Returns the ranges
+/// left over and how long the suffix is, which is up to the caller to push once it's done
+fn trim(a: &[usize], ar: Range<usize>, b: &[usize], br: Range<usize>, ops: &mut Vec<Op>) -> (Range<usize>, Range<usize>, usize) {
+    let prefix = common_prefix(a, ar.clone(), b, br.clone());
+    ops.extend(std::iter::repeat_n(Op::Equal, prefix));
+    let (ar, br) = (ar.start + prefix..ar.end, br.start + prefix..br.end);
+
+    let suffix = common_suffix(a, ar.clone(), b, br.clone());
+    (ar.start..ar.end - suffix, br.start..br.end - suffix, suffix)
+}
+
+fn common_prefix(a: &[usize], ar: Range<usize>, b: &[usize], br: Range<usize>) -> usize {
+    a[ar].iter().zip(&b[br]).take_while(|(x, y)| x == y).count()
+}
+
+fn common_suffix(a: &[usize], ar: Range<usize>, b: &[usize], br: Range<usize>) -> usize {
+    a[ar].iter().rev().zip(b[br].iter().rev()).take_while(|(x, y)| x == y).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A tiny xorshift so the tests don't need a crate for random numbers, and always see the same inputs
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+
+        // Few distinct values, so there's plenty to line up and plenty of ways to do it
+        fn ids(&mut self, max_len: usize) -> Vec<usize> {
+            let len = self.below(max_len + 1);
+            (0..len).map(|_| self.below(4)).collect()
+        }
+    }
+
+    /// Length of the longest common subsequence, the slow and obviously right way
+    fn lcs(a: &[usize], b: &[usize]) -> usize {
+        let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+        for i in 0..a.len() {
+            for j in 0..b.len() {
+                table[i + 1][j + 1] = match a[i] == b[j] {
+                    true => table[i][j] + 1,
+                    false => std::cmp::max(table[i][j + 1], table[i + 1][j]),
+                };
+            }
+        }
+        table[a.len()][b.len()]
+    }
+
+    /// Run `ops` over `a` and `b`, checking they only keep equal lines and use up both sides. Returns how many were kept
+    fn replay(ops: &[Op], a: &[usize], b: &[usize]) -> usize {
+        let (mut i, mut j, mut kept) = (0, 0, 0);
+        for op in ops {
+            match op {
+                Op::Equal => {
+                    assert_eq!(a[i], b[j], "kept lines that differ");
+                    i += 1;
+                    j += 1;
+                    kept += 1;
+                },
+                Op::Delete => i += 1,
+                Op::Insert => j += 1,
+            }
+        }
+        assert_eq!((i, j), (a.len(), b.len()), "ops don't cover both sides");
+        kept
+    }
+
+    #[test]
+    fn algorithms_keep_a_common_subsequence() {
+        let mut rng = Rng(0x2545f4914f6cdd1d);
+        for _ in 0..2000 {
+            let (a, b) = (rng.ids(12), rng.ids(12));
+            let best = lcs(&a, &b);
+
+            for algorithm in Algorithm::ALL {
+                let kept = replay(&algorithm.differ().diff(&a, &b), &a, &b);
+                assert!(kept <= best);
+                // Myers only gives up on a shortest diff for inputs far bigger than these
+                if matches!(algorithm, Algorithm::Myers | Algorithm::Minimal) {
+                    assert_eq!(kept, best, "{:?} isn't minimal for {:?} -> {:?}", algorithm, a, b);
+                }
+            }
+        }
+    }
+}
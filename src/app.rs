@@ -1,198 +1,461 @@
-use std::{error::Error, ffi::{OsStr, OsString}, fs::{self, File}, path::Path};
-use clap::Parser;
-use diffy::{self, DiffOptions};
-use ratatui::{crossterm::event::{KeyEventKind, MouseEventKind}, layout::{Constraint, Rect}, style::{Color, Style, Stylize}, text::{Line, Span}, widgets::{Block, Borders, Clear, Paragraph}, Terminal};
-use ratatui::crossterm::event::{self, Event, KeyCode};
-use syntect::{easy::HighlightLines, highlighting::ThemeSet, parsing::SyntaxSet};
-use syntect_tui::translate_colour;
-
-use crate::ui::{generate_block, Ui};
-use crate::helpers::compare_hashes;
+use std::{env, error::Error, fs, io, ops::Range, path::Path, process::Command, thread, time::{Duration, Instant}};
+use clap::{Parser, ValueEnum};
+use regex::bytes::Regex;
+use ratatui::{crossterm::event::{KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind}, layout::{Constraint, Layout, Margin, Position, Rect}, style::{Color, Style, Stylize}, text::{Line, Span}, widgets::{Block, Clear, Paragraph}, Terminal};
+use ratatui::crossterm::event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode};
+use ratatui::crossterm::{execute, terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen}};
+use syntect::{highlighting::{Theme, ThemeSet}, parsing::{SyntaxReference, SyntaxSet}};
+
+use crate::ui::{generate_block, Palette, Ui};
+use crate::algorithm::Algorithm;
+use crate::helpers::config_dir;
 use crate::args::Args;
+use crate::clipboard;
+use crate::compare::{Comparator, Whitespace};
+use crate::config::{Config, View};
+use crate::diff::{Diff, Kind, Progress, Row};
+use crate::edit::{Change, Editor};
+use crate::highlight::{find_syntax, LazyHighlighter, StyledLine};
+use crate::input::MappedFile;
+use crate::keymap::{Action, Keymap};
+use crate::moves::Moves;
+use crate::structured::{Columns, Document, Structured, Table};
+use crate::watch::Watcher;
+
+// How long messages stay in the status bar
+const MESSAGE_TIME: Duration = Duration::from_secs(2);
+
+// How long lines that changed on disk stay lit up
+const FLASH_TIME: Duration = Duration::from_secs(1);
+
+// How often to check on the files with --watch
+const WATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+// Quick diffs are done before the loading screen would even be seen, so don't flash it
+const LOADING_DELAY: Duration = Duration::from_millis(150);
+
+// Moves get looked for again once typing stops for this long, not on every key
+const MOVES_DELAY: Duration = Duration::from_millis(300);
+
+// How far the resize keys move the divider, in percent
+const SPLIT_STEP: u16 = 5;
+
+// Neither side can get smaller than this percent of the space, short of maximizing the other one
+const MIN_SPLIT: u16 = 10;
+
+/// Text picked out with the mouse or in visual mode. Positions are (diff row, column of the displayed line)
+#[derive(Clone, Copy, Debug)]
+struct Selection {
+    pane: usize,
+    anchor: (usize, usize),
+    head: (usize, usize),
+    // Whole rows, ignoring the columns
+    lines: bool,
+}
+
+impl Selection {
+    /// Whole rows of one side
+    fn rows(pane: usize, first: usize, last: usize) -> Self {
+        Self { pane, anchor: (first, 0), head: (last, 0), lines: true }
+    }
+
+    /// The start and end, in order
+    fn bounds(&self) -> ((usize, usize), (usize, usize)) {
+        if self.anchor <= self.head { (self.anchor, self.head) } else { (self.head, self.anchor) }
+    }
+
+    fn row_range(&self) -> Range<usize> {
+        let (start, end) = self.bounds();
+        start.0..end.0 + 1
+    }
+
+    fn contains(&self, row: usize, col: usize) -> bool {
+        if self.lines {
+            return self.row_range().contains(&row);
+        }
+
+        let (start, end) = self.bounds();
+        (start..=end).contains(&(row, col))
+    }
+}
+
+/// What the left mouse button is dragging around
+#[derive(Clone, Copy, Debug)]
+enum Drag {
+    Divider,
+    Minimap,
+    Select { pane: usize, anchor: (usize, usize) },
+}
 
 pub struct App {
-    current_line: usize,
-    current_col: usize,
-    args: Args
+    // Where each side is scrolled to. They move together while `locked`
+    current_line: [usize; 2],
+    current_col: [usize; 2],
+    locked: bool,
+    // The side clicked last
+    focus: usize,
+    // Percent of the space the left side gets
+    split: u16,
+    // The side taking up all of the space, if one is
+    maximized: Option<usize>,
+    selection: Option<Selection>,
+    // Shown in the status bar for a little while, like "copied"
+    message: Option<(String, Instant)>,
+    // The lines of each file that just changed on disk, lit up for a moment
+    flash: Option<([Vec<Range<usize>>; 2], Instant)>,
+    // Whether the movement keys extend the selection instead of scrolling
+    visual: bool,
+    drag: Option<Drag>,
+    // The file being edited, which sticks around with its undo history after leaving edit mode
+    editor: Option<Editor>,
+    // Whether keys go to the editor
+    editing: bool,
+    // Asking whether to save before quitting
+    confirm_quit: bool,
+    // The format the files are being diffed as, if they're being diffed by structure
+    format: Option<Structured>,
+    // Where each line of each side is in its document, when diffing by structure
+    paths: [Vec<String>; 2],
+    // How the columns line up, when diffing tables
+    columns: Option<Columns>,
+    args: Args,
+    config: Config,
+    keymap: Keymap
 }
 
 impl App {
-    pub fn new() -> Self {
+    pub fn new() -> Result<Self, Box<dyn Error>> {
         let args = Args::parse();
+        let config = Config::load(&args)?;
+        let keymap = Keymap::new(config.keys.preset, &config.keys.bindings)?;
+        let format = config.structured.resolve(args.files());
 
-        Self {
-            current_line: 0,
-            current_col: 0,
-            args: args
+        Ok(Self {
+            current_line: [0; 2],
+            current_col: [0; 2],
+            locked: true,
+            focus: 0,
+            split: 50,
+            maximized: None,
+            selection: None,
+            message: None,
+            flash: None,
+            visual: false,
+            drag: None,
+            editor: None,
+            editing: false,
+            confirm_quit: false,
+            format,
+            paths: [Vec::new(), Vec::new()],
+            columns: None,
+            args,
+            config,
+            keymap
+        })
+    }
+
+    /// The settings in effect if `--print-config` was passed
+    pub fn printable_config(&self) -> Result<Option<String>, Box<dyn Error>> {
+        if !self.args.print_config {
+            return Ok(None);
         }
+
+        self.config.to_toml().map(Some)
     }
 
     pub fn run<B: ratatui::backend::Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<(), Box<dyn Error>> {
-        let ts = ThemeSet::load_defaults();
-        let ps = SyntaxSet::load_defaults_newlines();
-
-        let theme = &ts.themes["base16-ocean.dark"];
-
-        
-        let mut backgrounds: Vec<Color> = Vec::new();
-        
-        let mut syntaxes = vec![
-            ps.find_syntax_for_file(&self.args.file_1),
-            ps.find_syntax_for_file(&self.args.file_2),
-            ].iter().map(|s| {
-                if s.is_ok() && s.as_ref().unwrap().is_some() {
-                    let syn = s.as_ref().unwrap().unwrap();
-                    if syn.name == ps.find_syntax_plain_text().name {
-                        backgrounds.push(Color::Rgb(0x12, 0x12, 0x12));
-                        None
-                    } else {
-                        backgrounds.push({
-                            if theme.settings.background.is_some() {
-                                translate_colour(theme.settings.background.unwrap()).unwrap_or(Color::Rgb(0x12, 0x12, 0x12))
-                            } else {
-                                Color::Rgb(0x12, 0x12, 0x12)
-                            }
-                        });
-                        Some(HighlightLines::new(syn, theme))
-                    }
-                } else {
-                    None
-                }
-            }).collect::<Vec<Option<HighlightLines>>>();
-            
-        // Compute the hashes to see if the files are the same
-        compare_hashes(&mut vec![File::open(&self.args.file_1)?, File::open(&self.args.file_2)?])?;
-        
-        // Hopefully this doesn't blow up your computer
-        let f1 = fs::read_to_string(&self.args.file_1)?;
-        let f2 = fs::read_to_string(&self.args.file_2)?;
+        let mut ts = ThemeSet::load_defaults();
+        let mut ps = SyntaxSet::load_defaults_newlines();
+
+        // Pick up any themes and syntaxes the user dropped in their config directory
+        if let Some(dir) = config_dir().map(|dir| dir.join("themes")).filter(|dir| dir.is_dir()) {
+            ts.add_from_folder(&dir)?;
+        }
+        if let Some(dir) = config_dir().map(|dir| dir.join("syntaxes")).filter(|dir| dir.is_dir()) {
+            let mut builder = ps.into_builder();
+            builder.add_from_folder(&dir, true)?;
+            ps = builder.build();
+        }
+
+        let theme_names = ts.themes.keys().cloned().collect::<Vec<String>>();
+        let theme_name = self.config.theme.clone().unwrap_or_default();
+        let mut theme_index = theme_names.iter()
+            .position(|name| *name == theme_name)
+            .ok_or_else(|| format!("Unknown theme {}, pick one of: {}", theme_name, theme_names.join(", ")))?;
+
+        let mut theme = &ts.themes[&theme_names[theme_index]];
+        let mut palette = Palette::from_theme(theme, &self.config.colors);
+
+        let ignore = self.config.ignore_matching_lines.iter()
+            .map(|pattern| Regex::new(pattern).map_err(|e| format!("Invalid pattern to ignore {}: {}", pattern, e)))
+            .collect::<Result<Vec<Regex>, String>>()?;
+
+        let watcher = match self.args.watch {
+            true => Some(Watcher::new(self.args.files())?),
+            false => None
+        };
+
+        let mut files = self.open_files(watcher.is_some(), true)?;
+
+        let mut masks = self.config.all_masks()?;
+        masks.extend(self.format.map(Structured::masks).unwrap_or_default());
+        let masks = masks.into_iter()
+            .map(|mask| match Regex::new(&mask.pattern) {
+                Ok(pattern) => Ok((pattern, mask.replace.into_bytes())),
+                Err(e) => Err(format!("Invalid pattern to mask {}: {}", mask.pattern, e)),
+            })
+            .collect::<Result<Vec<(Regex, Vec<u8>)>, String>>()?;
+        let cmp = Comparator::new(self.config.whitespace, ignore, self.config.ignore_case, masks);
+
+        // Structured views are printed in their format whatever the files are called
+        let format_name = self.format.map(Structured::name).filter(|name| ps.find_syntax_by_token(name).is_some());
+        let syntaxes = [
+            (self.args.syntax_left.as_ref(), self.args.files()[0]),
+            (self.args.syntax_right.as_ref(), self.args.files()[1]),
+            ].iter().zip(&files).map(|(&(name, path), file)| {
+                let first_line = file.lines_from(0).next().map(String::from_utf8_lossy).unwrap_or_default();
+                find_syntax(&ps, name.or(self.args.syntax.as_ref()).or(format_name.as_ref()).map(|n| n.as_str()), path, &first_line)
+            }).collect::<Result<Vec<Option<&SyntaxReference>>, Box<dyn Error>>>()?;
 
         if self.args.hex {
             // Do hex expansion here
         }
 
-        let num_characters = terminal.size()?.width / 2 - 10;
-        eprintln!("{}", num_characters);
-        
-        // Draw a loading screen in case the files are large so the user doesn't think our program sucks (as much)
-        terminal.draw(|frame| {
-            let l1 = Line::from("Computing the diffs between the files...");
-            let l2 = Line::from("This may take a while...");
-
-            let block = generate_block(String::new());
-            
-            let b = Ui::center_rect(frame.area(), Constraint::Length(l1.width() as u16), Constraint::Length(2));
-
-            let p = Paragraph::new(vec![l1, l2])
-                .alignment(ratatui::layout::Alignment::Center)
-                .style(Style::default().fg(Color::Yellow));
-
-            frame.render_widget(block, frame.area());
-            frame.render_widget(p, b);
-        })?;
-
-        // Set up the diff options based on cli args
-        let mut ops = DiffOptions::new();
-        ops.set_context_len(if self.args.suppress_common_lines {self.args.context_lines.unwrap_or(0)} else {usize::MAX});
-
-        let keybinds_text = vec![
-            "[n] next page",
-            "[l] last page",
-            "[h] help",
-            "[r] reset",
-            "[q] quit",
-        ];
+        let help_text = self.keymap.help();
+        let names = self.args.files().map(|path| {
+            Path::new(path).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+        });
 
-        // Compute the diffs
-        let diff1 = ops.create_patch(&f1, &f2);
+        // Compute the diffs, only keeping context around the changes if asked to
+        let context = match self.config.view {
+            View::Full => None,
+            View::Changes => Some(self.config.context_lines)
+        };
+        let Some(mut diff) = Self::compute_diff(terminal, &files, &cmp, self.config.algorithm, context, &palette, self.matched())? else {
+            // Cancelled before it finished
+            return Ok(());
+        };
 
-        // Get the formatted Lines for display for each frame, slicing them based 
-        let (left_colors, left_lines, right_colors, right_lines) = App::get_diff_lines(&diff1);
+        // Nothing to show if the files are the same, going by the comparator. When watching they might not stay that way
+        if watcher.is_none() && diff.hunk_count() == 0 {
+            return Err("There is no diff between the files".into());
+        }
 
-        let left_lines = Self::highlight_lines(&left_lines, &mut syntaxes[0], &ps);
-        let right_lines = Self::highlight_lines(&right_lines, &mut syntaxes[1], &ps);
+        // Highlighting happens as rows come on screen, so the first frame doesn't wait on the whole file
+        let mut highlighters = Self::highlighters(&syntaxes, theme, &ps);
 
-        // Put a limit on the self.current_line so it won't go off the page. Harder for horizontal scroll :(
-        let max_file_len = std::cmp::max(left_colors.len(), right_colors.len());
+        // Holds the theme to go back to while the picker is open
+        let mut theme_picker: Option<usize> = None;
         let mut max_height: usize = 0;
         let mut show_help: bool = false;
-        
+
+        // Where the panes were last drawn, for working out what got clicked
+        let mut layout = Ui::default();
+        let mut gutters = files.each_ref().map(Self::gutter_width);
+        let mut stats = diff.stats();
+        let mut moves = Moves::find(&diff, &files[0], &files[1], &cmp, self.config.moved);
+        // When the last edit was made, if the moves haven't caught up with it yet
+        let mut moves_stale: Option<Instant> = None;
+
         loop {
             terminal.draw(|frame| {
                 frame.render_widget(Clear, frame.area());
-                // Show the help screen if 'h' was pressed
+                // Show the help screen if it was asked for
                 if show_help {
-                    Ui::show_help(frame, &keybinds_text);
+                    Ui::show_help(frame, &help_text, &palette);
                     return;
                 }
-                
-                // Get the layout and min_width so that we show the same amount of characters per row
+
+                // Get the layout. The sides can be different widths once the divider gets dragged
                 let mut layout_rect = frame.area();
                 layout_rect.height -= 1;
 
-                let layout = Ui::new(layout_rect);
-                let min_width = layout.get_min_width();
-                max_height = layout.get_height();
+                let ui = Ui::new(layout_rect, self.split, self.maximized, self.columns.is_some());
+                max_height = ui.get_height();
+                layout = ui.clone();
 
-                let keybinds_rect = Rect::new(
+                let status_rect = Rect::new(
                     0, layout_rect.height, layout_rect.width, 1
                 );
+                let (left, right) = self.get_status(&diff, &names, stats, moves.len(), max_height, &palette);
+                let [left_rect, right_rect] = Layout::horizontal([Constraint::Fill(1), Constraint::Length(right.width() as u16)]).areas(status_rect);
 
-                frame.render_widget( 
-                    Block::default()
-                    .title(keybinds_text.join(" "))
-                    .title_alignment(ratatui::layout::Alignment::Center)
-                    .title_style(Style::default().fg(Color::Rgb(0xff, 0xff, 0xff)))
-                    .borders(Borders::TOP)
-                    .border_style(Style::default().fg(Color::Rgb(0x3a, 0x3a, 0x3a)))
-                    .style(Style::default().bg(Color::Rgb(0x12, 0x12, 0x12))), keybinds_rect
-                );
+                frame.render_widget(Block::default().style(Style::default().bg(palette.border)), status_rect);
+                frame.render_widget(Paragraph::new(left).fg(palette.foreground), left_rect);
+                frame.render_widget(Paragraph::new(right).fg(palette.foreground), right_rect);
 
-                layout.boxes.iter().enumerate().for_each(|(i, &b)| {
-                    let box_name = match i {
-                        0 => Path::new(&self.args.file_1.clone()).file_name().unwrap().to_os_string(),
-                        1 => Path::new(&self.args.file_2.clone()).file_name().unwrap().to_os_string(),
-                        _ => OsString::new()
-                    };
-                    let block = generate_block(box_name.into_string().unwrap());
+                ui.boxes.iter().enumerate().filter(|(_, b)| !b.is_empty()).for_each(|(i, &b)| {
+                    let dirty = self.editor.as_ref().is_some_and(|e| e.side == i && e.dirty);
+                    let mut block = generate_block(if dirty { format!("{} [+]", names[i]) } else { names[i].clone() }, &palette);
+                    if i == self.focus {
+                        block = block.border_style(Style::default().fg(palette.gutter));
+                    }
 
-                    let text = match i {
-                        0 => self.get_rect_lines(&left_lines, &b, &mut  syntaxes[i]),
-                        1 => self.get_rect_lines(&right_lines, &b, &mut syntaxes[i]),
-                        _ => Vec::new(),
+                    // Only the rows on screen ever get turned into strings
+                    let rows = diff.rows(self.current_line[i], b.height as usize).collect::<Vec<Row>>();
+                    // Tables get drawn from their cells instead, with nothing to highlight
+                    let highlighted = match self.columns {
+                        Some(_) => Vec::new(),
+                        None => Self::highlight_rows(&rows, &files[i], i, self.config.tab_width, &mut highlighters[i])
+                    };
+                    let text = match &self.columns {
+                        Some(columns) => self.get_table_lines(&rows, &files, i, columns, &palette),
+                        None => self.get_rect_lines(&highlighted, i, &b, syntaxes[i].is_some())
                     };
 
                     let paragraph = Paragraph::new(text)
                         .block(block)
-                        .bg(backgrounds[i])
+                        .fg(palette.foreground)
+                        .bg(palette.background)
                         .left_aligned();
-                    
-                    let shift = ((self.current_line + b.height as usize - 2) as f64).log10() as u16 + 3;
+
+                    let shift = gutters[i];
 
                     // Reduce width a little and shift over so we can render line numbers
-                    let mut text_rect = b.clone();
-                    text_rect.width = min_width - shift;
+                    let mut text_rect = b;
+                    text_rect.width = b.width.saturating_sub(shift);
                     text_rect.x += shift;
 
                     // Generate the box that the line numbers go into
-                    let mut line_numbers_rect = b.clone();
+                    let mut line_numbers_rect = b;
                     line_numbers_rect.width = shift;
 
-                    // let line_numbers = generate_line_numbers(self.current_line, b.height as usize);
-                    let line_numbers = match i {
-                        0 => self.get_line_numbers(&left_colors, b.height as usize),
-                        1 => self.get_line_numbers(&right_colors, b.height as usize),
-                        _ => Paragraph::new("")
-                    };
-                    
+                    let line_numbers = self.get_line_numbers(&diff, &moves, i, b.height as usize, &palette);
+
                     frame.render_widget(line_numbers, line_numbers_rect);
                     frame.render_widget(paragraph, text_rect);
+
+                    // The header row of a table, over the cells and scrolled sideways with them
+                    if let (Some(columns), Some(&header)) = (&self.columns, layout.headers.get(i)) {
+                        let mut header_rect = text_rect.inner(Margin::new(1, 0));
+                        header_rect.y = header.y;
+                        header_rect.height = header.height;
+                        frame.render_widget(Paragraph::new(self.get_table_header(columns, i, &palette)).bg(palette.background), header_rect);
+                    }
+
+                    if let Some(selection) = self.selection.filter(|s| s.pane == i) {
+                        let inner = text_rect.inner(Margin::new(1, 1));
+                        let buf = frame.buffer_mut();
+                        for y in inner.top()..inner.bottom() {
+                            let row = self.current_line[i] + (y - inner.y) as usize;
+                            if row >= diff.len() {
+                                break;
+                            }
+                            for x in inner.left()..inner.right() {
+                                if selection.contains(row, self.current_col[i] + (x - inner.x) as usize) {
+                                    buf[(x, y)].set_bg(palette.selection);
+                                }
+                            }
+                        }
+                    }
+
+                    // Dim the changes that only touch ignored lines
+                    let inner = text_rect.inner(Margin::new(1, 1));
+                    for (y, row) in (inner.top()..inner.bottom()).zip(&rows) {
+                        if row.ignored {
+                            for x in inner.left()..inner.right() {
+                                frame.buffer_mut()[(x, y)].set_fg(palette.gutter);
+                            }
+                        }
+                    }
+
+                    // Label the start of each change with where it is in the documents, over the end of the row
+                    if self.format.is_some() {
+                        for (y, row) in (inner.top()..inner.bottom()).zip(self.current_line[i]..) {
+                            let Some(path) = (diff.prev_hunk(row + 1) == Some(row)).then(|| self.row_path(&diff, row, i)).flatten() else {
+                                continue;
+                            };
+                            // Long paths lose their start, the end says the most about where the change is
+                            let label = format!(" {} ", path);
+                            let label = label.chars().skip(label.chars().count().saturating_sub((inner.width / 2) as usize)).collect::<String>();
+                            let x = inner.right().saturating_sub(label.chars().count() as u16);
+                            frame.buffer_mut().set_string(x, y, label, Style::default().fg(palette.gutter).bg(palette.background));
+                        }
+                    }
+
+                    // Put the terminal's cursor where the editor's is, if that's on screen
+                    if let Some(editor) = self.editor.as_ref().filter(|e| self.editing && e.side == i) {
+                        let inner = text_rect.inner(Margin::new(1, 1));
+                        let row = diff.row_of(i, editor.cursor.0).and_then(|row| row.checked_sub(self.current_line[i]));
+                        let col = editor.display_col(self.config.tab_width).checked_sub(self.current_col[i]);
+                        if let (Some(row), Some(col)) = (row, col) {
+                            let position = Position::new(inner.x.saturating_add(col as u16), inner.y.saturating_add(row as u16));
+                            if inner.contains(position) {
+                                frame.set_cursor_position(position);
+                            }
+                        }
+                    }
+
+                    // Light up the line numbers of whatever just changed on disk
+                    if let Some((flash, _)) = &self.flash {
+                        let inner = line_numbers_rect.inner(Margin::new(1, 1));
+                        let buf = frame.buffer_mut();
+                        for (y, row) in (inner.top()..inner.bottom()).zip(&rows) {
+                            let flashed = row.side(i).is_some_and(|n| {
+                                flash[i].get(flash[i].partition_point(|r| r.end <= n)).is_some_and(|r| r.contains(&n))
+                            });
+                            if flashed {
+                                for x in inner.left()..inner.right() {
+                                    buf[(x, y)].set_fg(palette.background).set_bg(palette.changed);
+                                }
+                            }
+                        }
+                    }
                 });
+
+                frame.render_widget(self.get_minimap(&diff, ui.minimap.height as usize, max_height, &palette), ui.minimap);
+
+                if theme_picker.is_some() {
+                    Ui::show_theme_picker(frame, &theme_names, theme_index, &palette);
+                }
+
+                if let Some(editor) = self.editor.as_ref().filter(|_| self.confirm_quit) {
+                    let question = format!("Save the changes to {}?", names[editor.side]);
+                    Ui::show_confirm(frame, &question, "[y] save  [n] don't save  [esc] cancel", &palette);
+                }
             })?;
 
                 // While loop so that we don't re-render the screen when nothing would've changed
                 let mut should_break = false;
+                let mut changed_on_disk: Option<[bool; 2]> = None;
                 while !should_break {
+                    // Wake up for anything that changes on its own: messages and flashes going away, and the files changing
+                    let timeout = [
+                        self.message.as_ref().map(|(_, shown)| MESSAGE_TIME.saturating_sub(shown.elapsed())),
+                        self.flash.as_ref().map(|(_, shown)| FLASH_TIME.saturating_sub(shown.elapsed())),
+                        watcher.as_ref().map(|_| WATCH_INTERVAL),
+                        moves_stale.map(|edited| MOVES_DELAY.saturating_sub(edited.elapsed())),
+                    ].into_iter().flatten().min();
+
+                    if let Some(timeout) = timeout {
+                        if !event::poll(timeout)? {
+                            let mut redraw = false;
+                            if self.message.as_ref().is_some_and(|(_, shown)| shown.elapsed() >= MESSAGE_TIME) {
+                                self.message = None;
+                                redraw = true;
+                            }
+                            if self.flash.as_ref().is_some_and(|(_, shown)| shown.elapsed() >= FLASH_TIME) {
+                                self.flash = None;
+                                redraw = true;
+                            }
+                            if moves_stale.is_some_and(|edited| edited.elapsed() >= MOVES_DELAY) {
+                                moves = Moves::find(&diff, &files[0], &files[1], &cmp, self.config.moved);
+                                moves_stale = None;
+                                redraw = true;
+                            }
+
+                            if let Some(changed) = watcher.as_ref().map(|w| w.changed()).filter(|c| c.contains(&true)) {
+                                changed_on_disk = Some(changed);
+                                redraw = true;
+                            }
+
+                            if redraw {
+                                break;
+                            }
+                            continue;
+                        }
+                    }
+
                     let event: Event = event::read()?;
                     match event {
                         Event::Key(key) =>  {
@@ -201,88 +464,198 @@ impl App {
                                 continue;
                             }
 
-                            // Enable quit, refresh, and vertical and horizontal scroll
-                            match key.code {
-                                KeyCode::Char('h') => {
+                            // The theme picker gets the keys while it's open
+                            if let Some(original) = theme_picker {
+                                match (key.code, self.keymap.action(&key)) {
+                                    (KeyCode::Up, _) | (_, Some(Action::ScrollUp)) => theme_index = theme_index.saturating_sub(1),
+                                    (KeyCode::Down, _) | (_, Some(Action::ScrollDown)) => theme_index = std::cmp::min(theme_index + 1, theme_names.len() - 1),
+                                    (KeyCode::Enter, _) => theme_picker = None,
+                                    (KeyCode::Esc, _) | (_, Some(Action::PickTheme)) => {
+                                        theme_index = original;
+                                        theme_picker = None;
+                                    },
+                                    _ => continue
+                                }
+
+                                // Re-highlight with whatever is selected so it can be previewed
+                                theme = &ts.themes[&theme_names[theme_index]];
+                                palette = Palette::from_theme(theme, &self.config.colors);
+                                highlighters = Self::highlighters(&syntaxes, theme, &ps);
+                                break;
+                            }
+
+                            if self.confirm_quit {
+                                match key.code {
+                                    KeyCode::Char('y') => {
+                                        should_break = self.save();
+                                        self.confirm_quit = false;
+                                    },
+                                    KeyCode::Char('n') => should_break = true,
+                                    KeyCode::Esc | KeyCode::Char('c') => self.confirm_quit = false,
+                                    _ => continue
+                                }
+                                break;
+                            }
+
+                            // Everything but leaving and saving goes to the editor in edit mode
+                            if self.editing {
+                                match self.keymap.action(&key) {
+                                    _ if key.code == KeyCode::Esc => self.editing = false,
+                                    Some(Action::Save) => {
+                                        self.save();
+                                    },
+                                    _ => {
+                                        let Some(editor) = self.editor.as_mut() else {
+                                            continue;
+                                        };
+                                        let side = editor.side;
+                                        if let Some(change) = editor.key(&key) {
+                                            self.apply_edit(terminal, change, &mut files, &mut diff, &cmp, context, &palette)?;
+                                            highlighters[side].invalidate(change.start);
+                                            gutters = files.each_ref().map(Self::gutter_width);
+                                            stats = diff.stats();
+                                            moves_stale = Some(Instant::now());
+                                        }
+                                        self.follow_cursor(&diff, max_height, &layout, &gutters);
+                                    }
+                                }
+                                break;
+                            }
+
+                            if self.visual && key.code == KeyCode::Esc {
+                                self.visual = false;
+                                self.selection = None;
+                                break;
+                            }
+
+                            let Some(action) = self.keymap.action(&key) else {
+                                continue;
+                            };
+
+                            match action {
+                                Action::Quit => {
+                                    if self.editor.as_ref().is_some_and(|e| e.dirty) {
+                                        self.confirm_quit = true;
+                                        break;
+                                    }
+                                    should_break = true;
+                                },
+                                Action::ToggleHelp => {
                                     show_help = !show_help;
                                     break;
                                 },
-                                KeyCode::Char('q') => should_break = true,
-                                KeyCode::Char('r') => {
-                                    self.current_col = 0;
-                                    self.current_line = 0;
+                                Action::PickTheme => {
+                                    theme_picker = Some(theme_index);
                                     break;
                                 },
-                                KeyCode::Char('e') => {
-                                    if max_height > max_file_len {
-                                        break;
+                                Action::SwitchSide => {
+                                    self.focus = 1 - self.focus;
+                                    if self.maximized.is_some() {
+                                        self.maximized = Some(self.focus);
                                     }
-                                    self.current_line = max_file_len - max_height +  3;
                                     break;
                                 },
-                                KeyCode::Char('b') => {
-                                    self.current_line = 0;
+                                Action::ShrinkLeft | Action::GrowLeft => {
+                                    let split = match action {
+                                        Action::ShrinkLeft => self.split.saturating_sub(SPLIT_STEP),
+                                        _ => self.split + SPLIT_STEP
+                                    };
+                                    self.split = split.clamp(MIN_SPLIT, 100 - MIN_SPLIT);
+                                    self.maximized = None;
+                                    break;
+                                },
+                                Action::Maximize => {
+                                    self.maximized = match self.maximized {
+                                        Some(_) => None,
+                                        None => Some(self.focus)
+                                    };
                                     break;
                                 },
-                                KeyCode::PageDown |
-                                KeyCode::Char('n') => {
-                                    if self.current_line + max_height  - 3 < max_file_len {
-                                        self.current_line += max_height - 5
+                                // Enable refresh, and vertical and horizontal scroll
+                                Action::ToggleLock => {
+                                    // Whatever is focused stays put when locking back up
+                                    self.locked = !self.locked;
+                                    if self.locked {
+                                        self.sync(self.focus);
                                     }
                                     break;
                                 },
-                                KeyCode::PageUp |
-                                KeyCode::Char('l') => {
-                                    self.current_line = self.current_line.saturating_sub(max_height - 5);
+                                Action::Sync => {
+                                    self.sync(self.focus);
                                     break;
                                 },
-                                KeyCode::Right => {
-                                    self.current_col += 1;
+                                Action::Visual => {
+                                    self.visual = !self.visual;
+                                    self.selection = self.visual.then(|| {
+                                        let row = self.current_line[self.focus];
+                                        Selection::rows(self.focus, row, row)
+                                    });
                                     break;
                                 },
-                                KeyCode::Left => {
-                                    // Don't scroll past beginning of line
-                                    if self.current_col > 0 {
-                                        self.current_col -= 1;
-                                        break;
-                                    }
+                                Action::CopyLine | Action::CopySelection | Action::CopyHunk | Action::CopyLeft | Action::CopyRight => {
+                                    self.copy(action, &files, &diff)?;
+                                    break;
                                 },
-                                KeyCode::Up => {
-                                    // Don't scroll past beginning
-                                    if self.current_line > 0 {
-                                        self.current_line -= 1;
-                                        break;
+                                Action::Edit => {
+                                    self.start_editing(&files, &diff);
+                                    self.follow_cursor(&diff, max_height, &layout, &gutters);
+                                    break;
+                                },
+                                Action::Save => {
+                                    self.save();
+                                    break;
+                                },
+                                Action::CycleAlgorithm => {
+                                    let algorithm = self.config.algorithm.next();
+                                    if let Some(new_diff) = Self::compute_diff(terminal, &files, &cmp, algorithm, context, &palette, self.matched())? {
+                                        self.config.algorithm = algorithm;
+                                        diff = new_diff;
+                                        stats = diff.stats();
+                                        moves = Moves::find(&diff, &files[0], &files[1], &cmp, self.config.moved);
+                                        moves_stale = None;
+                                        self.selection = None;
+                                        self.visual = false;
+
+                                        let last_line = (diff.len() + 3).saturating_sub(max_height);
+                                        self.current_line = self.current_line.map(|line| std::cmp::min(line, last_line));
+                                        let name = algorithm.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default();
+                                        self.message = Some((format!("diffing with {}", name), Instant::now()));
                                     }
+                                    break;
                                 },
-                                KeyCode::Down => {
-                                    // Down allow scroll too far down
-                                    if self.current_line + max_height  - 3 < max_file_len {
-                                        self.current_line += 1;
-                                        break;
+                                Action::JumpMove => {
+                                    self.jump_move(&diff, &moves, max_height);
+                                    break;
+                                },
+                                Action::OpenEditor => {
+                                    let (pane, row) = self.cursor();
+                                    if self.editor.as_ref().is_some_and(|e| e.dirty) {
+                                        self.message = Some((String::from("save the edits before opening an editor"), Instant::now()));
+                                    } else if let Some(line) = (row < diff.len()).then(|| diff.line_at(row, pane)) {
+                                        // Lines of a structured diff don't line up with the file, so just open it
+                                        let line = if self.format.is_some() { 0 } else { line };
+                                        // The editor might truncate the file, which would pull a mapping out from under us
+                                        files[pane].unmap();
+                                        if self.open_in_editor(terminal, pane, line)? {
+                                            changed_on_disk = Some([pane == 0, pane == 1]);
+                                        }
                                     }
+                                    break;
+                                },
+                                _ if self.visual => if self.move_cursor(action, &diff, max_height) {
+                                    break;
+                                },
+                                _ => if self.scroll(action, self.focus, &diff, max_height) {
+                                    break;
                                 }
-                                _ => continue
                             }
                         },
                         Event::Mouse(e) => {
-                            match e.kind {
-                                MouseEventKind::ScrollDown => {
-                                    // Down allow scroll too far down
-                                    if self.current_line + max_height  - 3 < max_file_len {
-                                        self.current_line += 1;
-                                        break;
-                                    }
-                                },
-                                MouseEventKind::ScrollUp => {
-                                    // Don't scroll past beginning
-                                    if self.current_line > 0 {
-                                        self.current_line -= 1;
-                                        break;
-                                    }
-                                }
-                                _ => continue
+                            if !show_help && theme_picker.is_none() && !self.confirm_quit && self.mouse(e, &layout, &gutters, &diff, max_height) {
+                                break;
                             }
                         }
+                        Event::Resize(..) => break,
                         _ => continue
                     }
                 }
@@ -290,74 +663,713 @@ impl App {
                 if should_break {
                     break;
                 }
+
+                if let Some(changed) = changed_on_disk {
+                    if self.editor.as_ref().is_some_and(|e| e.dirty) {
+                        // Reloading would throw away the edits
+                        self.message = Some((String::from("changed on disk, not reloading over unsaved edits"), Instant::now()));
+                    } else if self.reload(terminal, &mut files, &mut diff, changed, &cmp, context, &palette, max_height)? {
+                        highlighters = Self::highlighters(&syntaxes, theme, &ps);
+                        gutters = files.each_ref().map(Self::gutter_width);
+                        stats = diff.stats();
+                        moves = Moves::find(&diff, &files[0], &files[1], &cmp, self.config.moved);
+                        moves_stale = None;
+
+                        // Start the editor over from what's on disk now, unless that's just our own save
+                        if let Some(editor) = self.editor.take() {
+                            self.editor = match files[editor.side].bytes() == editor.bytes() {
+                                true => Some(editor),
+                                false => Editor::new(editor.side, &files[editor.side], editor.cursor.0).ok()
+                            };
+                            self.editing &= self.editor.is_some();
+                        }
+                    }
+                }
         }
-        
+
         Ok(())
     }
 
-    fn highlight_lines<'a>(lines: &'a Vec<String>, highlighter: &mut Option<HighlightLines<'a>>, syntax: &SyntaxSet) -> Vec<Vec<(syntect::highlighting::Style, &'a str)>> {
-        if highlighter.is_none() {
-            lines.iter().map(|line| {
-                vec![
-                    (syntect::highlighting::Style::default(), line.as_str())
-                ]
-            })
-            .collect::<Vec<Vec<(syntect::highlighting::Style, &str)>>>()
-        } else {
-            lines.iter().map(|line| {
-                highlighter.as_mut()
-                .unwrap()
-                .highlight_line(line, syntax)
-                .unwrap_or(Vec::new())
-            })
-            .collect::<Vec<Vec<(syntect::highlighting::Style, &str)>>>()
+    /// Move side `pane` for one of the scrolling actions, or both sides while they're locked together.
+    /// Returns whether anything moved
+    fn scroll(&mut self, action: Action, pane: usize, diff: &Diff, max_height: usize) -> bool {
+        let (lines, cols) = (self.current_line, self.current_col);
+
+        // Don't allow scrolling too far down
+        let last_line = (diff.len() + 3).saturating_sub(max_height);
+        let page = max_height.saturating_sub(5).max(1);
+
+        let panes = if self.locked { 0..2 } else { pane..pane + 1 };
+        for i in panes {
+            let (line, col) = (&mut self.current_line[i], &mut self.current_col[i]);
+
+            match action {
+                Action::ScrollUp => *line = line.saturating_sub(1),
+                Action::ScrollDown => *line = std::cmp::min(*line + 1, last_line),
+                Action::ScrollLeft => *col = col.saturating_sub(1),
+                Action::ScrollRight => *col += 1,
+                Action::PageUp => *line = line.saturating_sub(page),
+                Action::PageDown => *line = std::cmp::min(*line + page, last_line),
+                Action::HalfPageUp => *line = line.saturating_sub(page / 2),
+                Action::HalfPageDown => *line = std::cmp::min(*line + page / 2, last_line),
+                Action::Top => *line = 0,
+                Action::Bottom => *line = last_line,
+                Action::NextHunk => {
+                    if let Some(row) = diff.next_hunk(*line) {
+                        *line = row;
+                    }
+                },
+                Action::PrevHunk => {
+                    if let Some(row) = diff.prev_hunk(*line) {
+                        *line = row;
+                    }
+                },
+                Action::Reset => {
+                    *col = 0;
+                    *line = 0;
+                },
+                _ => {}
+            }
+        }
+
+        (lines, cols) != (self.current_line, self.current_col)
+    }
+
+    /// Line the other side up with side `pane`. Both sides show rows of the same diff, so the
+    /// matching line on the other side is on the same row
+    fn sync(&mut self, pane: usize) {
+        self.current_line[1 - pane] = self.current_line[pane];
+        self.current_col[1 - pane] = self.current_col[pane];
+    }
+
+    /// Move the end of the selection in visual mode, scrolling to keep it on screen
+    fn move_cursor(&mut self, action: Action, diff: &Diff, max_height: usize) -> bool {
+        let Some(selection) = self.selection.as_mut() else {
+            return false;
+        };
+
+        let last = diff.len().saturating_sub(1);
+        let page = max_height.saturating_sub(5).max(1);
+        let row = selection.head.0;
+
+        selection.head.0 = match action {
+            Action::ScrollUp => row.saturating_sub(1),
+            Action::ScrollDown => std::cmp::min(row + 1, last),
+            Action::PageUp => row.saturating_sub(page),
+            Action::PageDown => std::cmp::min(row + page, last),
+            Action::HalfPageUp => row.saturating_sub(page / 2),
+            Action::HalfPageDown => std::cmp::min(row + page / 2, last),
+            Action::Top => 0,
+            Action::Bottom => last,
+            Action::NextHunk => diff.next_hunk(row).unwrap_or(row),
+            Action::PrevHunk => diff.prev_hunk(row).unwrap_or(row),
+            _ => return self.scroll(action, self.focus, diff, max_height)
+        };
+
+        let (pane, row) = (selection.pane, selection.head.0);
+        let visible = max_height.saturating_sub(2).max(1);
+        let top = self.current_line[pane].clamp((row + 1).saturating_sub(visible), row);
+
+        let panes = if self.locked { 0..2 } else { pane..pane + 1 };
+        for i in panes {
+            self.current_line[i] = top;
+        }
+
+        true
+    }
+
+    /// Jump to the other end of the first moved block from the cursor down to the bottom of the view,
+    /// keeping the block at the same height on screen and picking it out
+    fn jump_move(&mut self, diff: &Diff, moves: &Moves, max_height: usize) {
+        let (pane, row) = self.cursor();
+        let visible = max_height.saturating_sub(2).max(1);
+
+        let found = diff.rows(row, if self.visual { 1 } else { visible })
+            .find_map(|r| moves.at(pane, r.side(pane)?));
+        let Some(m) = found else {
+            self.message = Some((String::from("no moved lines here"), Instant::now()));
+            return;
+        };
+
+        let other = 1 - pane;
+        let (Some(from), Some(to)) = (diff.row_of(pane, m.side(pane).start), diff.row_of(other, m.side(other).start)) else {
+            return;
+        };
+        let offset = from.saturating_sub(self.current_line[pane]);
+
+        let last_line = (diff.len() + 3).saturating_sub(max_height);
+        let line = std::cmp::min(to.saturating_sub(offset), last_line);
+        let panes = if self.locked { 0..2 } else { other..other + 1 };
+        for i in panes {
+            self.current_line[i] = line;
+        }
+
+        self.focus = other;
+        if self.maximized.is_some() {
+            self.maximized = Some(other);
+        }
+        self.visual = false;
+        let end = diff.row_of(other, m.side(other).end - 1).unwrap_or(to);
+        self.selection = Some(Selection::rows(other, to, end));
+    }
+
+    /// The side and row the copy commands work from: the end of the selection in visual mode,
+    /// otherwise the top of the focused side
+    fn cursor(&self) -> (usize, usize) {
+        match self.selection {
+            Some(selection) if self.visual => (selection.pane, selection.head.0),
+            _ => (self.focus, self.current_line[self.focus])
+        }
+    }
+
+    /// Put some of the files on the clipboard for one of the copy actions
+    fn copy(&mut self, action: Action, files: &[MappedFile; 2], diff: &Diff) -> Result<(), Box<dyn Error>> {
+        let (pane, row) = self.cursor();
+        if row >= diff.len() {
+            return Ok(());
+        }
+
+        // The change under the cursor, or the next one down
+        let hunk = diff.hunk_at(row).or_else(|| diff.next_hunk(row).and_then(|r| diff.hunk_at(r)));
+        let lines = |side: usize, rows: Range<usize>| diff.rows(rows.start, rows.len())
+            .filter_map(|r| r.side(side))
+            .map(|n| files[side].text(n..n + 1))
+            .collect::<String>();
+
+        let text = match action {
+            Action::CopyLine => diff.row(row).side(pane).map(|n| files[pane].text(n..n + 1).into_owned()),
+            Action::CopySelection => self.selection.map(|selection| self.selection_text(selection, files, diff)),
+            Action::CopyHunk => hunk.map(|rows| format!(
+                "--- {}\n+++ {}\n{}",
+                self.args.files()[0], self.args.files()[1], diff.patch(rows, &files[0], &files[1])
+            )),
+            // The rows picked out if there are any, otherwise the change
+            _ => {
+                let side = if action == Action::CopyLeft { 0 } else { 1 };
+                self.selection.map(|s| s.row_range()).or(hunk).map(|rows| lines(side, rows))
+            }
+        };
+
+        let message = match text {
+            Some(text) => {
+                clipboard::copy(&text)?;
+                format!("copied {} lines", text.lines().count())
+            },
+            None => String::from("nothing to copy")
+        };
+        self.message = Some((message, Instant::now()));
+
+        // Like yanking in vim, copying is the end of visual mode
+        if self.visual {
+            self.visual = false;
+            self.selection = None;
+        }
+
+        Ok(())
+    }
+
+    /// The selected text. Partial lines are cut at the columns they're shown at
+    fn selection_text(&self, selection: Selection, files: &[MappedFile; 2], diff: &Diff) -> String {
+        let file = &files[selection.pane];
+        let rows = selection.row_range();
+        let lines = rows.filter_map(|r| diff.row(r).side(selection.pane).map(|n| (r, n)));
+
+        if selection.lines {
+            return lines.map(|(_, n)| file.text(n..n + 1)).collect::<String>();
+        }
+
+        let (start, end) = selection.bounds();
+        lines.map(|(r, n)| {
+            let line = Self::file_lines(file, n, self.config.tab_width).next().unwrap_or_default();
+            let from = if r == start.0 { start.1 } else { 0 };
+            let to = if r == end.0 { end.1 + 1 } else { usize::MAX };
+
+            line.chars().skip(from).take(to.saturating_sub(from)).collect::<String>()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+    }
+
+    /// Start editing the focused side, picking up where it was left if it was edited before
+    fn start_editing(&mut self, files: &[MappedFile; 2], diff: &Diff) {
+        let side = self.focus;
+        if self.format.is_some() {
+            self.message = Some((String::from("can't edit a structured diff"), Instant::now()));
+            return;
+        }
+        if let Some(editor) = self.editor.as_ref().filter(|e| e.side != side && e.dirty) {
+            let names = self.args.files();
+            self.message = Some((format!("save {} before editing the other side", names[editor.side]), Instant::now()));
+            return;
+        }
+
+        if self.editor.as_ref().is_none_or(|e| e.side != side) {
+            let row = self.current_line[side];
+            let line = if diff.len() == 0 { 0 } else { diff.line_at(std::cmp::min(row, diff.len() - 1), side) };
+            match Editor::new(side, &files[side], line) {
+                Ok(editor) => self.editor = Some(editor),
+                Err(e) => {
+                    self.message = Some((e, Instant::now()));
+                    return;
+                }
+            }
+        }
+
+        self.editing = true;
+        self.visual = false;
+        self.selection = None;
+    }
+
+    /// Hand the terminal over to `$VISUAL` or `$EDITOR` with side `side` open at `line`, the way the terminal
+    /// gets put back when quitting. Returns whether the editor ran
+    fn open_in_editor<B: ratatui::backend::Backend>(&mut self, terminal: &mut Terminal<B>, side: usize, line: usize) -> Result<bool, Box<dyn Error>> {
+        let command = env::var("VISUAL").or_else(|_| env::var("EDITOR")).unwrap_or_else(|_| String::from("vi"));
+        let mut words = command.split_whitespace();
+        let Some(program) = words.next() else {
+            return Ok(false);
+        };
+
+        disable_raw_mode()?;
+        execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture)?;
+
+        let status = Command::new(program)
+            .args(words)
+            .arg(format!("+{}", line + 1))
+            .arg(self.args.files()[side])
+            .status();
+
+        enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture)?;
+        // Whatever the editor left on the screen is gone, so draw everything again
+        terminal.clear()?;
+
+        match status {
+            Ok(_) => Ok(true),
+            Err(e) => {
+                self.message = Some((format!("couldn't run {}: {}", program, e), Instant::now()));
+                Ok(false)
+            }
+        }
+    }
+
+    /// Write the edited file back where it came from. Returns whether it got saved
+    fn save(&mut self) -> bool {
+        let Some(editor) = self.editor.as_mut() else {
+            return true;
+        };
+
+        let path = self.args.files()[editor.side];
+        let (saved, message) = match editor.save(path) {
+            Ok(()) => (true, format!("saved {}", path)),
+            Err(e) => (false, format!("couldn't save: {}", e))
+        };
+        self.message = Some((message, Instant::now()));
+        saved
+    }
+
+    /// Swap in the edited file and bring the diff up to date with it
+    #[allow(clippy::too_many_arguments)]
+    fn apply_edit<B: ratatui::backend::Backend>(&mut self, terminal: &mut Terminal<B>, change: Change, files: &mut [MappedFile; 2], diff: &mut Diff, cmp: &Comparator, context: Option<usize>, palette: &Palette) -> Result<(), Box<dyn Error>> {
+        let Some(editor) = self.editor.as_ref() else {
+            return Ok(());
+        };
+        let side = editor.side;
+
+        // Only the changed lines get copied over, once the file is being kept as lines like the editor's
+        let old = change.start..change.start + change.removed;
+        let len = files[side].len();
+        if !files[side].splice(old.clone(), editor.lines(change.start..change.start + change.inserted)) {
+            files[side] = editor.file();
+        }
+
+        // The editor can have an empty last line that the file doesn't count. The rows can't be patched
+        // up when the two disagree about the lines, so diff the whole thing again
+        let lined_up = len + change.inserted == files[side].len() + change.removed && files[side].len() == editor.len();
+        if lined_up && diff.update(&files[0], &files[1], cmp, self.config.algorithm, context, side, old, change.inserted) {
+            return Ok(());
+        }
+        match Self::compute_diff(terminal, files, cmp, self.config.algorithm, context, palette, self.matched())? {
+            Some(new_diff) => *diff = new_diff,
+            None => {
+                // The old rows don't fit the edited file any more, so pair the lines up as they are
+                let rows = (0..std::cmp::max(files[0].len(), files[1].len()))
+                    .map(|n| [(n < files[0].len()).then_some(n), (n < files[1].len()).then_some(n)])
+                    .collect::<Vec<[Option<usize>; 2]>>();
+                *diff = Diff::aligned(&rows, &files[0], &files[1], cmp, context);
+                self.message = Some((String::from("diff cancelled, lines are paired up as they are"), Instant::now()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Scroll the edited side so the editor's cursor is on screen
+    fn follow_cursor(&mut self, diff: &Diff, max_height: usize, layout: &Ui, gutters: &[u16; 2]) {
+        let Some(editor) = self.editor.as_ref() else {
+            return;
+        };
+        let side = editor.side;
+        let Some(row) = diff.row_of(side, editor.cursor.0) else {
+            return;
+        };
+
+        let visible = max_height.saturating_sub(2).max(1);
+        let width = layout.boxes.get(side).map_or(0, |b| b.width.saturating_sub(gutters[side] + 2)).max(1) as usize;
+        let col = editor.display_col(self.config.tab_width);
+
+        let line = self.current_line[side].clamp((row + 1).saturating_sub(visible), row);
+        let current_col = self.current_col[side].clamp((col + 1).saturating_sub(width), col);
+
+        let panes = if self.locked { 0..2 } else { side..side + 1 };
+        for i in panes {
+            self.current_line[i] = line;
+            self.current_col[i] = current_col;
+        }
+    }
+
+    /// Handle a mouse event. Returns whether anything needs redrawing
+    fn mouse(&mut self, e: MouseEvent, layout: &Ui, gutters: &[u16; 2], diff: &Diff, max_height: usize) -> bool {
+        let shift = e.modifiers.contains(KeyModifiers::SHIFT);
+        let action = match e.kind {
+            MouseEventKind::ScrollDown if shift => Action::ScrollRight,
+            MouseEventKind::ScrollUp if shift => Action::ScrollLeft,
+            MouseEventKind::ScrollDown => Action::ScrollDown,
+            MouseEventKind::ScrollUp => Action::ScrollUp,
+            MouseEventKind::ScrollRight => Action::ScrollRight,
+            MouseEventKind::ScrollLeft => Action::ScrollLeft,
+            MouseEventKind::Down(MouseButton::Left) => return self.click(e, layout, gutters, diff),
+            MouseEventKind::Drag(MouseButton::Left) => return self.drag(e, layout, gutters, diff),
+            MouseEventKind::Up(MouseButton::Left) => {
+                self.drag = None;
+                return false;
+            },
+            _ => return false
+        };
+
+        // The wheel scrolls whichever side it's over
+        let pane = layout.boxes.iter()
+            .position(|b| b.contains(Position::new(e.column, e.row)))
+            .unwrap_or(self.focus);
+        self.scroll(action, pane, diff, max_height)
+    }
+
+    fn click(&mut self, e: MouseEvent, layout: &Ui, gutters: &[u16; 2], diff: &Diff) -> bool {
+        let boxes = &layout.boxes;
+
+        // The borders on either side of the gap between the boxes grab the divider
+        if layout.on_divider(Position::new(e.column, e.row)) {
+            self.drag = Some(Drag::Divider);
+            return false;
+        }
+
+        if layout.minimap.contains(Position::new(e.column, e.row)) {
+            self.drag = Some(Drag::Minimap);
+            return self.jump(e, layout, diff);
+        }
+
+        let Some(i) = boxes.iter().position(|b| b.contains(Position::new(e.column, e.row))) else {
+            return false;
+        };
+        self.focus = i;
+        self.selection = None;
+        self.visual = false;
+
+        if let Some((row, col)) = self.position_at(i, boxes[i], gutters[i], e, diff) {
+            if e.column < boxes[i].x + gutters[i] {
+                // Clicking a line number picks the whole change it's part of
+                self.selection = diff.hunk_at(row).map(|rows| Selection::rows(i, rows.start, rows.end - 1));
+            } else {
+                self.drag = Some(Drag::Select { pane: i, anchor: (row, col) });
+            }
+        }
+
+        true
+    }
+
+    fn drag(&mut self, e: MouseEvent, layout: &Ui, gutters: &[u16; 2], diff: &Diff) -> bool {
+        match self.drag {
+            Some(Drag::Divider) => {
+                let split = layout.split_at(Position::new(e.column, e.row)).clamp(MIN_SPLIT, 100 - MIN_SPLIT);
+
+                std::mem::replace(&mut self.split, split) != split
+            },
+            Some(Drag::Minimap) => self.jump(e, layout, diff),
+            Some(Drag::Select { pane, anchor }) => {
+                // Keep the selection in its own pane even if the mouse wanders off
+                let b = layout.boxes[pane];
+                let inner = b.inner(Margin::new(1, 1));
+                // A pane too small to have any text in it has nothing to select, and nothing to clamp to
+                if inner.height == 0 || inner.width <= gutters[pane] {
+                    return false;
+                }
+                let mut e = e;
+                e.row = e.row.clamp(inner.top(), inner.bottom().saturating_sub(1));
+                e.column = e.column.clamp(inner.left() + gutters[pane], inner.right().saturating_sub(1));
+
+                let Some(head) = self.position_at(pane, b, gutters[pane], e, diff) else {
+                    return false;
+                };
+                self.selection = Some(Selection { pane, anchor, head, lines: false });
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Center the view on the part of the diff under the mouse in the minimap
+    fn jump(&mut self, e: MouseEvent, layout: &Ui, diff: &Diff) -> bool {
+        let minimap = layout.minimap;
+        if minimap.is_empty() {
+            return false;
+        }
+        let max_height = layout.get_height();
+        let y = e.row.clamp(minimap.top(), minimap.bottom().saturating_sub(1)) - minimap.y;
+
+        let row = y as usize * diff.len() / minimap.height.max(1) as usize;
+        let last_line = (diff.len() + 3).saturating_sub(max_height);
+        let line = std::cmp::min(row.saturating_sub(max_height.saturating_sub(2) / 2), last_line);
+
+        let lines = self.current_line;
+        let panes = if self.locked { 0..2 } else { self.focus..self.focus + 1 };
+        for i in panes {
+            self.current_line[i] = line;
+        }
+
+        lines != self.current_line
+    }
+
+    /// The row and column of the text under the mouse in side `pane`, drawn in `b`, if there's a row there
+    fn position_at(&self, pane: usize, b: Rect, gutter: u16, e: MouseEvent, diff: &Diff) -> Option<(usize, usize)> {
+        let inner = b.inner(Margin::new(1, 1));
+        if !inner.contains(Position::new(e.column, e.row)) {
+            return None;
+        }
+
+        let row = self.current_line[pane] + (e.row - inner.y) as usize;
+        // The text has a border of its own just past the gutter
+        let col = self.current_col[pane] + e.column.saturating_sub(b.x + gutter + 1) as usize;
+
+        (row < diff.len()).then_some((row, col))
+    }
+
+    /// How wide the line numbers for a file are, borders included
+    fn gutter_width(file: &MappedFile) -> u16 {
+        (file.len().max(1) as f64).log10() as u16 + 3
+    }
+
+    /// Open both files, or parse them and print them out the same way if diffing by structure. With
+    /// `fall_back`, files only picked to be parsed by their extension get a line diff if they don't parse
+    fn open_files(&mut self, read: bool, fall_back: bool) -> Result<[MappedFile; 2], Box<dyn Error>> {
+        let paths = self.args.files().map(String::from);
+
+        if let Some(format) = self.format {
+            let [left, right] = paths.each_ref().map(|path| fs::read(path).map_err(|e| format!("Couldn't read {}: {}", path, e)));
+            let bytes = [left?, right?];
+            let failed = |i: usize, e: String| format!("{} {}", paths[i], e);
+
+            let parsed = match format.is_table() {
+                true => match [0, 1].map(|i| Table::parse(format, &bytes[i]).map_err(|e| failed(i, e))) {
+                    [Ok(left), Ok(right)] => {
+                        let (documents, columns) = Document::tables([left, right], self.args.key_column.as_deref())?;
+                        self.columns = Some(columns);
+                        Ok(documents)
+                    },
+                    [Err(e), _] | [_, Err(e)] => Err(e),
+                },
+                false => match [0, 1].map(|i| Document::parse(format, &bytes[i]).map_err(|e| failed(i, e))) {
+                    [Ok(left), Ok(right)] => Ok([left, right]),
+                    [Err(e), _] | [_, Err(e)] => Err(e),
+                },
+            };
+
+            match parsed {
+                Ok([left, right]) => {
+                    self.paths = [left.paths, right.paths];
+                    return Ok([MappedFile::from_bytes(left.text), MappedFile::from_bytes(right.text)]);
+                },
+                Err(e) if fall_back && self.config.structured == Structured::Auto => {
+                    self.message = Some((format!("{}, showing a line diff", e), Instant::now()));
+                    self.format = None;
+                },
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        // Map the files instead of reading them so only the lines we look at get paged in. Files being
+        // watched get read in, since saving over a mapped file can pull it out from under us
+        let open = if read { MappedFile::read } else { MappedFile::open };
+        Ok([open(&paths[0])?, open(&paths[1])?])
+    }
+
+    /// Read the files again after `changed` ones changed on disk and redo the diff, keeping the view on the
+    /// same unchanged lines. Returns whether the files got swapped out
+    #[allow(clippy::too_many_arguments)]
+    fn reload<B: ratatui::backend::Backend>(&mut self, terminal: &mut Terminal<B>, files: &mut [MappedFile; 2], diff: &mut Diff, changed: [bool; 2], cmp: &Comparator, context: Option<usize>, palette: &Palette, max_height: usize) -> Result<bool, Box<dyn Error>> {
+        // Files can be missing for a moment in the middle of a save. There'll be another event when it's back
+        let reloaded = match self.open_files(true, false) {
+            Ok(files) => files,
+            Err(e) => {
+                self.message = Some((format!("couldn't reload: {}", e), Instant::now()));
+                return Ok(false);
+            }
+        };
+
+        let Some(new_diff) = Self::compute_diff(terminal, &reloaded, cmp, self.config.algorithm, context, palette, self.matched())? else {
+            return Ok(false);
+        };
+
+        // Diff each file against what it was to find the lines to light up
+        let exact = Comparator::new(Whitespace::Exact, Vec::new(), false, Vec::new());
+        let flash = [0, 1].map(|i| match changed[i] {
+            true => Diff::compute(&files[i], &reloaded[i], &exact, self.config.algorithm, None, &Progress::default())
+                .map(|d| d.changed_lines(1))
+                .unwrap_or_default(),
+            false => Vec::new()
+        });
+
+        let anchors = self.anchors(diff, max_height);
+        *files = reloaded;
+        *diff = new_diff;
+
+        // Line numbers in a file that didn't change still point at the same lines
+        let side = if changed[0] && !changed[1] { 1 } else { 0 };
+        let last_line = (diff.len() + 3).saturating_sub(max_height);
+        for (i, anchor) in anchors.into_iter().enumerate() {
+            let row = anchor.and_then(|(row, offset)| {
+                let line = row.side(side)?;
+                Some(diff.row_of(side, line)?.saturating_add_signed(-offset))
+            });
+            self.current_line[i] = std::cmp::min(row.unwrap_or(self.current_line[i]), last_line);
+        }
+
+        self.selection = None;
+        self.visual = false;
+        self.flash = Some((flash, Instant::now()));
+        Ok(true)
+    }
+
+    /// For each side, the closest unchanged row to the top of the view and how far below the top it is.
+    /// If the whole view is one change, the row just before it
+    fn anchors(&self, diff: &Diff, max_height: usize) -> [Option<(Row, isize)>; 2] {
+        [0, 1].map(|i| {
+            let top = self.current_line[i];
+            diff.rows(top, max_height)
+                .zip(0..)
+                .find(|(row, _)| row.kind == Kind::Context)
+                .or_else(|| {
+                    let before = diff.hunk_at(top)?.start.checked_sub(1)?;
+                    Some((diff.row(before), before as isize - top as isize))
+                })
+        })
+    }
+
+    /// Diff the files on a worker thread, keeping the loading screen up to date and listening for quit
+    fn compute_diff<B: ratatui::backend::Backend>(terminal: &mut Terminal<B>, files: &[MappedFile; 2], cmp: &Comparator, algorithm: Algorithm, context: Option<usize>, palette: &Palette, matched: Option<&[[Option<usize>; 2]]>) -> Result<Option<Diff>, Box<dyn Error>> {
+        // Rows that are already lined up don't need diffing
+        if let Some(matched) = matched {
+            return Ok(Some(Diff::aligned(matched, &files[0], &files[1], cmp, context)));
         }
+
+        let progress = Progress::default();
+        let total = files[0].len() + files[1].len();
+
+        let started = Instant::now();
+
+        thread::scope(|s| {
+            let worker = s.spawn(|| Diff::compute(&files[0], &files[1], cmp, algorithm, context, &progress));
+
+            while !worker.is_finished() {
+                if started.elapsed() >= LOADING_DELAY {
+                    terminal.draw(|frame| Ui::show_loading(frame, progress.get(), total, palette))?;
+                }
+
+                // Redrawing on a timer also takes care of resizes
+                if !event::poll(Duration::from_millis(20))? {
+                    continue;
+                }
+
+                if let Event::Key(key) = event::read()? {
+                    let ctrl_c = key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c');
+                    if key.kind == KeyEventKind::Press && (key.code == KeyCode::Char('q') || ctrl_c) {
+                        progress.cancel();
+                    }
+                }
+            }
+
+            worker.join().map_err(|_| "The diff thread panicked".into())
+        })
+    }
+
+    /// The rows of a table diff lined up by a key column, if there is one
+    fn matched(&self) -> Option<&[[Option<usize>; 2]]> {
+        self.columns.as_ref().and_then(|columns| columns.matched.as_deref())
     }
 
-    fn get_rect_lines<'a>(&self, lines: &'a Vec<Vec<(syntect::highlighting::Style, &'a str)>>, b: &Rect, highlighter: &mut Option<HighlightLines<'a>>) -> Vec<Line<'a>> {
-        lines.iter().skip(self.current_line).take(b.height as usize).map(|line| {
-            let len = line.iter().map(|s| s.1.len()).sum();
+    fn highlighters<'a>(syntaxes: &[Option<&'a SyntaxReference>], theme: &'a Theme, ps: &'a SyntaxSet) -> [LazyHighlighter<'a>; 2] {
+        [
+            LazyHighlighter::new(syntaxes[0], theme, ps),
+            LazyHighlighter::new(syntaxes[1], theme, ps),
+        ]
+    }
+
+    /// Highlight side `i` of the rows. The highlighter only ever sees the file's own lines, in order,
+    /// so the filler rows can't throw off things like block comments
+    fn highlight_rows(rows: &[Row], file: &MappedFile, i: usize, tab_width: usize, highlighter: &mut LazyHighlighter) -> Vec<StyledLine> {
+        let lines = rows.iter().filter_map(|row| row.side(i)).collect::<Vec<usize>>();
+        let mut highlighted = highlighter.highlight(&lines, |start| Self::file_lines(file, start, tab_width)).into_iter();
+
+        rows.iter().map(|row| {
+            match row.side(i) {
+                Some(_) => highlighted.next().unwrap_or_default(),
+                None => StyledLine::new()
+            }
+        })
+        .collect::<Vec<StyledLine>>()
+    }
+
+    /// Iterate over the lines of the file as they get displayed, starting at line `start`
+    fn file_lines(file: &MappedFile, start: usize, tab_width: usize) -> impl Iterator<Item = String> + '_ {
+        file.lines_from(start).map(move |line| {
+            String::from_utf8_lossy(line).trim_end().replace("\t", &" ".repeat(tab_width))
+        })
+    }
+
+    fn get_rect_lines<'a>(&self, lines: &'a [StyledLine], i: usize, b: &Rect, highlighted: bool) -> Vec<Line<'a>> {
+        let current_col = self.current_col[i];
+        lines.iter().map(|line| {
+            // Columns count characters, not bytes, so lines with anything past ASCII get cut in the right places
+            let len = line.iter().map(|s| s.1.chars().count()).sum::<usize>();
             let mut size_so_far = 0;
 
-            if len <= self.current_col {
+            if len <= current_col {
                 return Line::from("");
             }
 
-            if highlighter.is_none() {
-                let s = line.iter().map(|s| s.1).collect::<Vec<&str>>().join("");
-                Line::from(
-                    s[
-                        self.current_col..
-                        std::cmp::min(
-                            len,
-                            self.current_col + b.width as usize
-                        )
-                    ].to_string()
-                )
+            if !highlighted {
+                let s = line.iter().map(|s| s.1.as_str()).collect::<Vec<&str>>().join("");
+                Line::from(s.chars().skip(current_col).take(b.width as usize).collect::<String>())
             } else {
                 Line::from(
                     line
-                    .into_iter()
+                    .iter()
                     .map(|segment| {
                         let len = segment.1.chars().count();
-                        
+
                         let fg = Color::Rgb(segment.0.foreground.r, segment.0.foreground.g, segment.0.foreground.b);
 
                         // Don't use background color -- skip
                         let style = Style::default().fg(fg);
 
                         let ret;
-                        
-                        if size_so_far + segment.1.len() <= self.current_col {
+
+                        if size_so_far + len <= current_col {
                             ret = Span::from("");
-                        } else if size_so_far < self.current_col && size_so_far + segment.1.len() > self.current_col {
+                        } else if size_so_far < current_col {
                             ret = Span::styled(
-                                segment.1[self.current_col.saturating_sub(size_so_far)..]
-                                .to_string(),
+                                segment.1.chars().skip(current_col - size_so_far).collect::<String>(),
                                 style
                             );
                         } else {
-                            ret = Span::styled(segment.1, style);
+                            ret = Span::styled(segment.1.as_str(), style);
                         }
 
                         size_so_far += len;
@@ -371,56 +1383,205 @@ impl App {
         .collect::<Vec<Line>>()
     }
 
-    fn get_line_numbers<'a>(&self, colors: &Vec<char>, height: usize) -> Paragraph<'a> {
+    /// The column names over side `i` of a table. Columns only one side has get its color, and on the
+    /// other side they're dimmed, keeping their space so the columns line up
+    fn get_table_header<'a>(&self, columns: &Columns, i: usize, palette: &Palette) -> Line<'a> {
+        let cells = columns.names.iter().zip(&columns.sides).map(|(name, sides)| {
+            let style = match sides {
+                [true, true] => Style::default().fg(palette.foreground),
+                _ if !sides[i] => Style::default().fg(palette.gutter),
+                _ => Style::default().fg(if i == 0 { palette.removed } else { palette.added }),
+            };
+            (name.clone(), style.bold())
+        });
+        self.table_line(cells, columns, i, palette)
+    }
+
+    /// Side `i` of the rows as a table. Cells that differ from the other side's get lit up, and the cells of
+    /// columns only one side has get its color
+    fn get_table_lines<'a>(&self, rows: &[Row], files: &[MappedFile; 2], i: usize, columns: &Columns, palette: &Palette) -> Vec<Line<'a>> {
+        let cells = |i: usize, line: usize| columns.cells(i, files[i].lines_from(line).next().unwrap_or_default());
+
+        rows.iter().map(|row| {
+            let Some(line) = row.side(i) else {
+                return Line::from("");
+            };
+            let mine = cells(i, line);
+            let theirs = row.side(1 - i).filter(|_| row.kind == Kind::Change).map(|line| cells(1 - i, line));
+
+            let styled = mine.into_iter().enumerate().map(|(c, cell)| {
+                let style = match (&theirs, columns.sides[c]) {
+                    (_, [true, true]) if theirs.as_ref().is_some_and(|t| t[c] != cell) => Style::default().fg(palette.changed).bold(),
+                    (_, [true, true]) => Style::default().fg(palette.foreground),
+                    _ => Style::default().fg(if i == 0 { palette.removed } else { palette.added }),
+                };
+                (cell.unwrap_or_default(), style)
+            });
+            self.table_line(styled, columns, i, palette)
+        })
+        .collect::<Vec<Line>>()
+    }
+
+    /// Cells padded out to their columns' widths with lines between them, scrolled over to side `i`'s column
+    fn table_line<'a>(&self, cells: impl Iterator<Item = (String, Style)>, columns: &Columns, i: usize, palette: &Palette) -> Line<'a> {
+        let separator = Style::default().fg(palette.gutter);
+        let mut skip = self.current_col[i];
+        let mut spans = Vec::new();
+
+        for (c, (cell, style)) in cells.enumerate() {
+            let padding = columns.widths[c].saturating_sub(cell.chars().count());
+            let pieces = [
+                (String::from(if c == 0 { "" } else { " \u{2502} " }), separator),
+                (cell, style),
+                (" ".repeat(padding), Style::default()),
+            ];
+            for (text, style) in pieces {
+                let len = text.chars().count();
+                if skip >= len {
+                    skip -= len;
+                    continue;
+                }
+                spans.push(Span::styled(text.chars().skip(skip).collect::<String>(), style));
+                skip = 0;
+            }
+        }
+
+        Line::from(spans)
+    }
+
+    /// Where in the documents the change at `row` is, or the last one before it, when diffing by structure
+    fn path_at<'a>(&'a self, diff: &Diff, row: usize) -> Option<&'a str> {
+        self.format?;
+        let row = match diff.hunk_at(row) {
+            Some(_) => row,
+            None => diff.prev_hunk(row)?
+        };
+        self.row_path(diff, row, 0)
+    }
+
+    /// Where in the documents row `row` is, going by side `i` unless it only has a line on the other side
+    fn row_path<'a>(&'a self, diff: &Diff, row: usize, i: usize) -> Option<&'a str> {
+        let row = diff.row(row);
+        let (side, line) = row.side(i).map(|line| (i, line)).or(row.side(1 - i).map(|line| (1 - i, line)))?;
+        self.paths[side].get(line).map(String::as_str)
+    }
+
+    /// The left and right halves of the status bar
+    fn get_status<'a>(&self, diff: &Diff, names: &[String; 2], stats: (usize, usize, usize), moved: usize, max_height: usize, palette: &Palette) -> (Line<'a>, Line<'a>) {
+        let (inserted, deleted, changed) = stats;
+
+        let mut left = vec![
+            Span::from(format!(" {} \u{2194} {}  ", names[0], names[1])),
+            Span::styled(format!("+{} ", inserted), Style::default().fg(palette.added)),
+            Span::styled(format!("-{} ", deleted), Style::default().fg(palette.removed)),
+            Span::styled(format!("~{}", changed), Style::default().fg(palette.changed)),
+        ];
+        if moved > 0 {
+            left.push(Span::styled(format!(" \u{21c4}{}", moved), Style::default().fg(palette.moved)));
+        }
+        if diff.hunk_count() > 0 {
+            let top = self.current_line[self.focus];
+            left.push(Span::from(match diff.hunk_index(top) {
+                0 => format!("  {} changes", diff.hunk_count()),
+                n => format!("  change {}/{}", n, diff.hunk_count())
+            }));
+            if let Some(path) = self.path_at(diff, top) {
+                left.push(Span::styled(format!("  {}", path), Style::default().fg(palette.changed)));
+            }
+        }
+        if let Some((message, _)) = &self.message {
+            left.push(Span::styled(format!("  {}", message), Style::default().fg(palette.changed)));
+        }
+
+        // Where each side is, by the line at the top or at the end of the selection in visual mode
+        let mut right = [0, 1].map(|i| {
+            let row = match self.selection {
+                Some(selection) if self.visual && selection.pane == i => selection.head.0,
+                _ => self.current_line[i]
+            };
+            let line = if diff.len() == 0 { 0 } else { diff.line_at(std::cmp::min(row, diff.len() - 1), i) + 1 };
+            format!("{} {}:{}", ["L", "R"][i], line, self.current_col[i] + 1)
+        })
+        .join(" ");
+
+        let last_line = (diff.len() + 3).saturating_sub(max_height);
+        let percent = (self.current_line[self.focus] * 100).checked_div(last_line).unwrap_or(100);
+        right += &format!("  {}%", percent);
+
+        let modes = [
+            (self.config.whitespace != Whitespace::Exact)
+                .then(|| self.config.whitespace.to_possible_value().map(|v| v.get_name().to_string()))
+                .flatten(),
+            (self.config.algorithm != Algorithm::Myers)
+                .then(|| self.config.algorithm.to_possible_value().map(|v| v.get_name().to_string()))
+                .flatten(),
+            self.args.hex.then(|| String::from("hex")),
+            self.format.map(Structured::name),
+            (!self.locked).then(|| String::from("unlocked")),
+            self.visual.then(|| String::from("visual")),
+            self.editing.then(|| String::from("edit")),
+            self.maximized.map(|_| String::from("maximized")),
+        ];
+        for mode in modes.into_iter().flatten() {
+            right += &format!(" [{}]", mode);
+        }
+
+        if let Some(hint) = self.keymap.hint(Action::ToggleHelp) {
+            right += &format!("  {} ", hint);
+        }
+
+        (Line::from(left), Line::from(right))
+    }
+
+    /// The whole diff squashed into `height` cells, with the changes marked and the part on screen lit up
+    fn get_minimap<'a>(&self, diff: &Diff, height: usize, max_height: usize, palette: &Palette) -> Paragraph<'a> {
+        let top = self.current_line[self.focus];
+        let visible = top..top + max_height.saturating_sub(2);
+
         Paragraph::new(
-                colors.iter().enumerate().skip(self.current_line).take(height).map(|(i, &c)| {
-                Line::styled((i.saturating_add(1)).to_string(),
+            (0..height).map(|y| {
+                // Every cell covers at least one row, even when the diff is shorter than the minimap
+                let start = y * diff.len() / height;
+                let end = std::cmp::max((y + 1) * diff.len() / height, start + 1);
+                if start >= diff.len() {
+                    return Line::styled(" ", Style::default().bg(palette.background));
+                }
+
+                let bg = if start < visible.end && visible.start < end { palette.selection } else { palette.background };
+                let fg = match diff.summary(start..end) {
+                    Kind::Context => return Line::styled(" ", Style::default().bg(bg)),
+                    Kind::Delete => palette.removed,
+                    Kind::Insert => palette.added,
+                    Kind::Change => palette.changed,
+                };
+
+                Line::styled("\u{2590}", Style::default().fg(fg).bg(bg))
+            })
+            .collect::<Vec<Line>>()
+        )
+    }
+
+    fn get_line_numbers<'a>(&self, diff: &Diff, moves: &Moves, i: usize, height: usize, palette: &Palette) -> Paragraph<'a> {
+        Paragraph::new(
+                diff.rows(self.current_line[i], height).map(|row| {
+                // Filler rows don't have a line number
+                let number = row.side(i).map(|n| (n + 1).to_string()).unwrap_or_default();
+                let moved = row.side(i).is_some_and(|n| moves.at(i, n).is_some());
+                Line::styled(number,
                     Style::default().fg(
-                        match c {
-                            'g' => Color::Green,
-                            'r' => Color::Red,
-                            _ => Color::DarkGray,
+                        match (i, row.kind) {
+                            _ if row.ignored => palette.gutter,
+                            _ if moved => palette.moved,
+                            (0, Kind::Delete) => palette.removed,
+                            (1, Kind::Insert) => palette.added,
+                            (_, Kind::Change) => palette.changed,
+                            _ => palette.gutter,
                         }
-                    ).bg(Color::Rgb(0x12, 0x12, 0x12))
+                    ).bg(palette.background)
                 )
             })
             .collect::<Vec<Line>>()
-        ).block(generate_block(String::new()))
+        ).block(generate_block(String::new(), palette))
         .left_aligned()
     }
-
-    fn get_diff_lines<'a>(patch: &'a diffy::Patch<'a, str>) -> (Vec<char>, Vec<String>, Vec<char>, Vec<String>) {
-        let len = patch.hunks().iter().map(|hunk| hunk.lines().len()).sum();
-        let mut left_colors: Vec<char> = Vec::with_capacity(len);
-        let mut right_colors: Vec<char> = Vec::with_capacity(len);
-        let mut left_lines: Vec<String> = Vec::with_capacity(len);
-        let mut right_lines: Vec<String> = Vec::with_capacity(len);
-
-        for hunk in patch.hunks() {
-            for line in hunk.lines() {
-                match line.to_owned() {
-                    diffy::Line::Context(l) => {
-                        left_colors.push('c');
-                        right_colors.push('c');
-                        left_lines.push(String::from(l.trim_end().replace("\t", &" ".repeat(4))));
-                        right_lines.push(String::from(l.trim_end().replace("\t", &" ".repeat(4))));
-                    },
-                    diffy::Line::Delete(l) => {
-                        left_colors.push('r');
-                        right_colors.push('c');
-                        left_lines.push(String::from(l.trim_end().replace("\t", &" ".repeat(4))));
-                        right_lines.push(String::new());
-                    },
-                    diffy::Line::Insert(l) => {
-                        left_colors.push('c');
-                        right_colors.push('g');
-                        left_lines.push(String::new());
-                        right_lines.push(String::from(l.trim_end().replace("\t", &" ".repeat(4))));
-                    }
-                }
-            }
-        }
-
-        (left_colors, left_lines, right_colors, right_lines)
-    }
 }
@@ -1,30 +1,98 @@
 use clap::Parser;
 
+use crate::algorithm::Algorithm;
+use crate::compare::Whitespace;
+use crate::config::View;
+use crate::moves::MoveDetection;
+use crate::structured::Structured;
+
 #[derive(Parser, Debug)]
 #[command(
     name = "rdiff3",
     author = "Caleb Kornegay <caleb.kornegay@gmail.com>",
     version = "0.0.3",
     about = "A TUI app to visually diff two text files",
-    long_about = "This tool shows a side-by-side diff of two files with a terminal interface\nAuthor: Caleb Kornegay <caleb.kornegay@gmail.com>"
+    long_about = "This tool shows a side-by-side diff of two files with a terminal interface\nAuthor: Caleb Kornegay <caleb.kornegay@gmail.com>",
+    // The files are only optional for --print-config, so don't make them look optional
+    override_usage = "rdiff3 [OPTIONS] <FILE_1> <FILE_2>\n       rdiff3 [OPTIONS] --print-config"
 )]
-
 pub struct Args {
-    #[arg(help = "First file")]
-    pub file_1: String,
+    #[arg(help = "First file", required_unless_present = "print_config")]
+    pub file_1: Option<String>,
 
-    #[arg(help = "Second file")]
-    pub file_2: String,
+    #[arg(help = "Second file", required_unless_present = "print_config")]
+    pub file_2: Option<String>,
 
     #[arg(short = 'x', long)]
     pub hex: bool,
 
-    #[arg(long)]
+    #[arg(long, help = "Same as --view changes")]
     pub suppress_common_lines: bool,
 
+    #[arg(long, value_enum, help = "Show the whole files or only the changes")]
+    pub view: Option<View>,
+
     #[arg(short = 'w', long)]
     pub width: Option<usize>,
 
     #[arg(short = 'c', long)]
     pub context_lines: Option<usize>,
+
+    #[arg(long, value_enum, help = "How differences in whitespace count")]
+    pub whitespace: Option<Whitespace>,
+
+    #[arg(long, value_enum, help = "How to line up the lines of the files")]
+    pub algorithm: Option<Algorithm>,
+
+    #[arg(long, value_enum, value_name = "FORMAT", help = "Parse the files and diff them by structure, picked by extension by default")]
+    pub structured: Option<Structured>,
+
+    #[arg(long, value_name = "COLUMN", help = "Line up the rows of CSV and TSV files by this column, by name or counting from 1, instead of by where they are")]
+    pub key_column: Option<String>,
+
+    #[arg(short = 'I', long, value_name = "REGEX", help = "Don't count changes where every line matches REGEX. Can be given more than once")]
+    pub ignore_matching_lines: Vec<String>,
+
+    #[arg(short = 'i', long, help = "Ignore differences in case")]
+    pub ignore_case: bool,
+
+    #[arg(long, value_name = "PATH", help = "Mask text matching the patterns in this file before comparing, see `masks` in the config. Can be given more than once")]
+    pub mask_file: Vec<String>,
+
+    #[arg(long, value_enum, help = "How to find blocks of lines that moved")]
+    pub moved: Option<MoveDetection>,
+
+    #[arg(long, value_name = "N", help = "How many spaces a tab is shown as")]
+    pub tab_width: Option<usize>,
+
+    #[arg(long, value_name = "PATH", help = "Config file to use instead of config.toml in the config directory")]
+    pub config: Option<String>,
+
+    #[arg(long, help = "Print the settings in effect as a config file and exit")]
+    pub print_config: bool,
+
+    #[arg(long, value_name = "NAME", help = "Syntax highlighting theme, built in or a .tmTheme file in the config themes directory")]
+    pub theme: Option<String>,
+
+    #[arg(long, help = "Default to a theme that suits a light terminal")]
+    pub light: bool,
+
+    #[arg(long, value_name = "LANG", help = "Syntax to highlight both files with, by name or extension")]
+    pub syntax: Option<String>,
+
+    #[arg(long, value_name = "LANG", help = "Syntax for the first file, overrides --syntax")]
+    pub syntax_left: Option<String>,
+
+    #[arg(long, value_name = "LANG", help = "Syntax for the second file, overrides --syntax")]
+    pub syntax_right: Option<String>,
+
+    #[arg(long, help = "Reload the diff whenever either file changes on disk")]
+    pub watch: bool,
+}
+
+impl Args {
+    /// Both file paths. They're only missing with --print-config, which never opens them
+    pub fn files(&self) -> [&str; 2] {
+        [&self.file_1, &self.file_2].map(|path| path.as_deref().unwrap_or_default())
+    }
 }
@@ -0,0 +1,22 @@
+use std::{env, io::{self, Write}};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+/// Put `text` on the system clipboard by asking the terminal to do it with an OSC 52 sequence.
+/// That goes wherever the terminal is, so it works over SSH and doesn't need a clipboard daemon
+pub fn copy(text: &str) -> io::Result<()> {
+    let sequence = format!("\x1b]52;c;{}\x07", STANDARD.encode(text));
+
+    // tmux and screen eat escape sequences they don't know about unless they're wrapped up for the outer
+    // terminal. tmux also needs `set -g allow-passthrough on` (or `set-clipboard on`) to pass it along
+    let sequence = if env::var_os("TMUX").is_some() {
+        format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+    } else if env::var("TERM").is_ok_and(|term| term.starts_with("screen")) {
+        format!("\x1bP{}\x1b\\", sequence)
+    } else {
+        sequence
+    };
+
+    let mut stdout = io::stdout();
+    stdout.write_all(sequence.as_bytes())?;
+    stdout.flush()
+}
@@ -0,0 +1,101 @@
+use std::borrow::Cow;
+use clap::ValueEnum;
+use regex::bytes::Regex;
+use serde::{Deserialize, Serialize};
+
+/// How much differences in whitespace count when comparing lines
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Whitespace {
+    /// Every byte counts
+    #[default]
+    Exact,
+    /// Ignore whitespace at the end of lines
+    IgnoreTrailing,
+    /// Treat runs of whitespace like a single space, and ignore it at the end of lines
+    IgnoreChange,
+    /// Ignore all whitespace
+    IgnoreAll,
+}
+
+/// Text to swap out of lines before they're compared, like IDs or timestamps that differ every run.
+/// `replace` can use `$1` or `$name` for groups in `pattern`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Mask {
+    pub pattern: String,
+    #[serde(default)]
+    pub replace: String,
+}
+
+/// Turns lines into what actually gets compared. Two lines are the same if their keys are
+pub struct Comparator {
+    whitespace: Whitespace,
+    // Changes made up only of lines matching one of these don't count
+    ignore: Vec<Regex>,
+    ignore_case: bool,
+    // Applied in order before whitespace is looked at
+    masks: Vec<(Regex, Vec<u8>)>,
+}
+
+impl Comparator {
+    pub fn new(whitespace: Whitespace, ignore: Vec<Regex>, ignore_case: bool, masks: Vec<(Regex, Vec<u8>)>) -> Self {
+        Self { whitespace, ignore, ignore_case, masks }
+    }
+
+    pub fn ignores_lines(&self) -> bool {
+        !self.ignore.is_empty()
+    }
+
+    /// Whether a line matches one of the patterns being ignored
+    pub fn ignores(&self, line: &[u8]) -> bool {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        self.ignore.iter().any(|r| r.is_match(line))
+    }
+
+    pub fn key<'a>(&self, line: &'a [u8]) -> Cow<'a, [u8]> {
+        if self.masks.is_empty() && !self.ignore_case {
+            return self.whitespace(line);
+        }
+
+        let mut masked = line.to_vec();
+        for (pattern, replace) in &self.masks {
+            if let Cow::Owned(replaced) = pattern.replace_all(&masked, replace.as_slice()) {
+                masked = replaced;
+            }
+        }
+        if self.ignore_case {
+            masked = fold_case(masked);
+        }
+        Cow::Owned(self.whitespace(&masked).into_owned())
+    }
+
+    fn whitespace<'a>(&self, line: &'a [u8]) -> Cow<'a, [u8]> {
+        match self.whitespace {
+            Whitespace::Exact => Cow::Borrowed(line),
+            Whitespace::IgnoreTrailing => Cow::Borrowed(line.trim_ascii_end()),
+            Whitespace::IgnoreChange => {
+                let mut key = Vec::with_capacity(line.len());
+                for &b in line.trim_ascii_end() {
+                    if !b.is_ascii_whitespace() {
+                        key.push(b);
+                    } else if key.last() != Some(&b' ') {
+                        key.push(b' ');
+                    }
+                }
+                Cow::Owned(key)
+            },
+            Whitespace::IgnoreAll => Cow::Owned(line.iter().copied().filter(|b| !b.is_ascii_whitespace()).collect::<Vec<u8>>()),
+        }
+    }
+}
+
+/// Close enough to Unicode case folding: going through upper case first makes "ß" and "SS" both
+/// come out as "ss". Lines that aren't UTF-8 only get ASCII folded
+fn fold_case(line: Vec<u8>) -> Vec<u8> {
+    match String::from_utf8(line) {
+        Ok(text) if text.is_ascii() => text.to_ascii_lowercase().into_bytes(),
+        Ok(text) => text.to_uppercase().to_lowercase().into_bytes(),
+        Err(e) => e.into_bytes().to_ascii_lowercase(),
+    }
+}
@@ -0,0 +1,171 @@
+use std::{collections::BTreeMap, error::Error, fs, path::PathBuf};
+use clap::ValueEnum;
+use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
+
+use crate::algorithm::Algorithm;
+use crate::args::Args;
+use crate::compare::{Mask, Whitespace};
+use crate::helpers::config_dir;
+use crate::keymap::Preset;
+use crate::moves::MoveDetection;
+use crate::structured::Structured;
+
+/// Which rows to show
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum View {
+    /// The whole files
+    #[default]
+    Full,
+    /// Only the changes, with `context-lines` around them
+    Changes,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Colors {
+    pub added: Color,
+    pub removed: Color,
+    pub changed: Color,
+    pub moved: Color,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Self {
+            added: Color::Green,
+            removed: Color::Red,
+            changed: Color::Yellow,
+            moved: Color::Cyan,
+        }
+    }
+}
+
+/// Key bindings: a preset, plus keys mapped to action names (or "none" to unbind them)
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keys {
+    pub preset: Preset,
+    pub bindings: BTreeMap<String, String>,
+}
+
+/// A file of masks to share, like one for a team's logs. Written like `masks` in the config file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaskFile {
+    masks: Vec<Mask>,
+}
+
+/// Everything that can go in the config file. Once loaded, it holds the settings in effect
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub theme: Option<String>,
+    pub tab_width: usize,
+    pub context_lines: usize,
+    pub view: View,
+    pub whitespace: Whitespace,
+    pub ignore_matching_lines: Vec<String>,
+    pub ignore_case: bool,
+    pub masks: Vec<Mask>,
+    pub mask_files: Vec<String>,
+    pub algorithm: Algorithm,
+    pub structured: Structured,
+    pub moved: MoveDetection,
+    pub colors: Colors,
+    pub keys: Keys,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            theme: None,
+            tab_width: 4,
+            context_lines: 0,
+            view: View::Full,
+            whitespace: Whitespace::Exact,
+            ignore_matching_lines: Vec::new(),
+            ignore_case: false,
+            masks: Vec::new(),
+            mask_files: Vec::new(),
+            algorithm: Algorithm::Myers,
+            structured: Structured::Auto,
+            moved: MoveDetection::Exact,
+            colors: Colors::default(),
+            keys: Keys::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Read `--config`, or `config.toml` in the config directory if there is one, then let the command line override it
+    pub fn load(args: &Args) -> Result<Self, Box<dyn Error>> {
+        let path = args.config.as_ref()
+            .map(PathBuf::from)
+            .or_else(|| config_dir().map(|dir| dir.join("config.toml")).filter(|path| path.exists()));
+
+        let mut config = match path {
+            Some(path) => {
+                let text = fs::read_to_string(&path).map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+                toml::from_str::<Config>(&text).map_err(|e| format!("Invalid config {}: {}", path.display(), e))?
+            },
+            None => Config::default()
+        };
+
+        if args.theme.is_some() {
+            config.theme = args.theme.clone();
+        }
+        config.theme.get_or_insert_with(|| String::from(
+            if args.light { "base16-ocean.light" } else { "base16-ocean.dark" }
+        ));
+
+        if let Some(tab_width) = args.tab_width {
+            config.tab_width = tab_width;
+        }
+        if let Some(context_lines) = args.context_lines {
+            config.context_lines = context_lines;
+        }
+        if args.suppress_common_lines {
+            config.view = View::Changes;
+        }
+        if let Some(view) = args.view {
+            config.view = view;
+        }
+        if let Some(whitespace) = args.whitespace {
+            config.whitespace = whitespace;
+        }
+        config.ignore_matching_lines.extend(args.ignore_matching_lines.iter().cloned());
+        if args.ignore_case {
+            config.ignore_case = true;
+        }
+        config.mask_files.extend(args.mask_file.iter().cloned());
+        if let Some(algorithm) = args.algorithm {
+            config.algorithm = algorithm;
+        }
+        if let Some(structured) = args.structured {
+            config.structured = structured;
+        }
+        if let Some(moved) = args.moved {
+            config.moved = moved;
+        }
+
+        Ok(config)
+    }
+
+    /// The masks given inline, then the ones from each mask file in order
+    pub fn all_masks(&self) -> Result<Vec<Mask>, Box<dyn Error>> {
+        let mut masks = self.masks.clone();
+        for path in &self.mask_files {
+            let text = fs::read_to_string(path).map_err(|e| format!("Couldn't read {}: {}", path, e))?;
+            let file = toml::from_str::<MaskFile>(&text).map_err(|e| format!("Invalid mask file {}: {}", path, e))?;
+            masks.extend(file.masks);
+        }
+        Ok(masks)
+    }
+
+    /// The settings as they'd be written in the config file
+    pub fn to_toml(&self) -> Result<String, Box<dyn Error>> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+}
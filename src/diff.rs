@@ -0,0 +1,849 @@
+use std::{borrow::Cow, collections::HashMap, ops::Range, sync::atomic::{AtomicBool, AtomicUsize, Ordering}};
+use crate::algorithm::{longest_increasing, Algorithm, Op};
+use crate::compare::Comparator;
+use crate::input::MappedFile;
+
+// Lines per side handed to the diff algorithm at once. Only a window's worth of lines
+// is ever held in memory, whatever the size of the files
+const WINDOW: usize = 8192;
+
+// How far a window with no lines in common on both sides grows looking for some
+const MAX_SPAN: usize = 64 * WINDOW;
+
+// How many lines of a common prefix to get through between progress updates
+const PROGRESS_STEP: usize = 1 << 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Context,
+    Delete,
+    Insert,
+    // A deleted line shown next to the line inserted in its place
+    Change,
+}
+
+/// A run of rows of the same kind, pointing at consecutive lines in the files
+#[derive(Clone, Copy, Debug)]
+struct Segment {
+    kind: Kind,
+    row: usize,
+    left: usize,
+    right: usize,
+    len: usize,
+    // Part of a hunk made up only of lines matching --ignore-matching-lines, which counts as unchanged
+    ignored: bool,
+}
+
+impl Segment {
+    /// Whether the rows are a change that counts
+    fn is_change(&self) -> bool {
+        self.kind != Kind::Context && !self.ignored
+    }
+
+    /// Rows `rows` of the segment, counting from its start
+    fn cut(&self, rows: Range<usize>) -> Self {
+        Self {
+            left: self.left + if self.kind == Kind::Insert { 0 } else { rows.start },
+            right: self.right + if self.kind == Kind::Delete { 0 } else { rows.start },
+            len: rows.len(),
+            ..*self
+        }
+    }
+
+    /// The first line on side `i`, if the segment has any lines on that side
+    fn side(&self, i: usize) -> Option<usize> {
+        match (i, self.kind) {
+            (0, Kind::Insert) | (1, Kind::Delete) => None,
+            (0, _) => Some(self.left),
+            (_, _) => Some(self.right),
+        }
+    }
+}
+
+/// One row of the side-by-side view. Sides without a line are drawn as filler
+#[derive(Clone, Copy, Debug)]
+pub struct Row {
+    pub kind: Kind,
+    pub left: Option<usize>,
+    pub right: Option<usize>,
+    // Changed, but only in lines that are being ignored
+    pub ignored: bool,
+}
+
+impl Row {
+    /// The line shown on side `i` (0 is left, 1 is right)
+    pub fn side(&self, i: usize) -> Option<usize> {
+        match i {
+            0 => self.left,
+            _ => self.right,
+        }
+    }
+}
+
+/// Shared between the thread computing a diff and the UI waiting on it
+#[derive(Default)]
+pub struct Progress {
+    done: AtomicUsize,
+    cancelled: AtomicBool,
+}
+
+impl Progress {
+    /// How many lines of both files combined have been diffed so far
+    pub fn get(&self) -> usize {
+        self.done.load(Ordering::Relaxed)
+    }
+
+    fn set(&self, done: usize) {
+        self.done.store(done, Ordering::Relaxed);
+    }
+
+    /// Ask the diff to stop as soon as it can
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// The aligned rows of a diff, stored as runs so memory scales with the number of changes
+pub struct Diff {
+    segments: Vec<Segment>,
+    rows: usize,
+    // The first row of every hunk
+    hunks: Vec<usize>,
+}
+
+impl Diff {
+    /// Diff two files. `context` limits how many unchanged lines are kept around each change.
+    /// Returns `None` if `progress` gets cancelled before it's done
+    pub fn compute(left: &MappedFile, right: &MappedFile, cmp: &Comparator, algorithm: Algorithm, context: Option<usize>, progress: &Progress) -> Option<Self> {
+        let mut segments: Vec<Segment> = Vec::new();
+
+        // Strip the common prefix and suffix before doing any real work
+        let mut prefix = 0;
+        for (l, r) in left.lines_from(0).zip(right.lines_from(0)) {
+            if cmp.key(l) != cmp.key(r) {
+                break;
+            }
+
+            prefix += 1;
+            if prefix % PROGRESS_STEP == 0 {
+                if progress.is_cancelled() {
+                    return None;
+                }
+                progress.set(2 * prefix);
+            }
+        }
+
+        let suffix = left.lines_rev()
+            .zip(right.lines_rev())
+            .take(std::cmp::min(left.len(), right.len()) - prefix)
+            .take_while(|(l, r)| cmp.key(l) == cmp.key(r) && !progress.is_cancelled())
+            .count();
+
+        if progress.is_cancelled() {
+            return None;
+        }
+
+        push(&mut segments, Kind::Context, 0, 0, prefix);
+
+        let (end_l, end_r) = (left.len() - suffix, right.len() - suffix);
+        let (mut a, mut b) = (prefix, prefix);
+
+        while a < end_l || b < end_r {
+            // Lines that appear exactly once on both sides are safe to line up. Everything
+            // after the last one gets diffed again with the next window. If everything's in
+            // this window, the algorithm gets to see all of it. A window with none grows, since
+            // one side can have run ahead of the other by more than a window
+            let mut span = WINDOW;
+            let (window_l, window_r, last, anchors) = loop {
+                let window_l = left.lines_from(a).take(std::cmp::min(span, end_l - a)).collect::<Vec<&[u8]>>();
+                let window_r = right.lines_from(b).take(std::cmp::min(span, end_r - b)).collect::<Vec<&[u8]>>();
+                let last = a + window_l.len() == end_l && b + window_r.len() == end_r;
+                let anchors = match last && a == prefix {
+                    true => Vec::new(),
+                    false => unique_anchors(&window_l, &window_r, cmp)
+                };
+
+                if !anchors.is_empty() || last || span >= MAX_SPAN {
+                    break (window_l, window_r, last, anchors);
+                }
+                span *= 2;
+            };
+            let (mut i, mut j) = (0, 0);
+
+            for &(ai, aj) in &anchors {
+                diff_gap(&mut segments, left, right, cmp, algorithm, a + i..a + ai, b + j..b + aj);
+                push(&mut segments, Kind::Context, a + ai, b + aj, 1);
+                i = ai + 1;
+                j = aj + 1;
+            }
+
+            if last {
+                diff_gap(&mut segments, left, right, cmp, algorithm, a + i..a + window_l.len(), b + j..b + window_r.len());
+                i = window_l.len();
+                j = window_r.len();
+            } else if anchors.is_empty() {
+                // Nothing in common for a long way, so a window's worth of each side really is different
+                i = std::cmp::min(WINDOW, window_l.len());
+                j = std::cmp::min(WINDOW, window_r.len());
+                diff_gap(&mut segments, left, right, cmp, algorithm, a..a + i, b..b + j);
+            }
+
+            a += i;
+            b += j;
+
+            if progress.is_cancelled() {
+                return None;
+            }
+            progress.set(a + b);
+        }
+
+        push(&mut segments, Kind::Context, end_l, end_r, suffix);
+
+        segments = pair_changes(segments);
+        mark_ignored(&mut segments, left, right, cmp);
+
+        if let Some(context) = context {
+            segments = trim_context(segments, context);
+        }
+
+        progress.set(left.len() + right.len());
+
+        Some(Self::from_segments(segments))
+    }
+
+    /// Lay out rows that are already matched up, like table rows lined up by a key column. Lines
+    /// on both sides are unchanged if they compare equal and a change if not
+    pub fn aligned(rows: &[[Option<usize>; 2]], left: &MappedFile, right: &MappedFile, cmp: &Comparator, context: Option<usize>) -> Self {
+        let mut segments: Vec<Segment> = Vec::new();
+        let (mut lines_l, mut lines_r) = (left.lines_from(0), right.lines_from(0));
+        // Where the next line of each side would go, for the sides a row doesn't have
+        let (mut l, mut r) = (0, 0);
+
+        for row in rows {
+            match *row {
+                [Some(i), Some(j)] => {
+                    let same = lines_l.next().zip(lines_r.next()).is_some_and(|(a, b)| cmp.key(a) == cmp.key(b));
+                    push(&mut segments, if same { Kind::Context } else { Kind::Change }, i, j, 1);
+                    (l, r) = (i + 1, j + 1);
+                },
+                [Some(i), None] => {
+                    lines_l.next();
+                    push(&mut segments, Kind::Delete, i, r, 1);
+                    l = i + 1;
+                },
+                [None, Some(j)] => {
+                    lines_r.next();
+                    push(&mut segments, Kind::Insert, l, j, 1);
+                    r = j + 1;
+                },
+                [None, None] => {},
+            }
+        }
+
+        mark_ignored(&mut segments, left, right, cmp);
+
+        if let Some(context) = context {
+            segments = trim_context(segments, context);
+        }
+
+        Self::from_segments(segments)
+    }
+
+    fn from_segments(mut segments: Vec<Segment>) -> Self {
+        let mut rows = 0;
+        for segment in segments.iter_mut() {
+            segment.row = rows;
+            rows += segment.len;
+        }
+
+        let mut diff = Self { segments, rows, hunks: Vec::new() };
+        diff.hunks = (0..diff.segments.len())
+            .filter(|&i| diff.starts_hunk(i))
+            .map(|i| diff.segments[i].row)
+            .collect::<Vec<usize>>();
+
+        diff
+    }
+
+    /// Catch up with lines `old` of side `i` getting replaced by `inserted` lines, given the files after
+    /// the edit. Only the hunks around the edit get diffed again. Returns false if the edit couldn't be
+    /// placed, in which case the whole thing needs computing again
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(&mut self, left: &MappedFile, right: &MappedFile, cmp: &Comparator, algorithm: Algorithm, context: Option<usize>, i: usize, old: Range<usize>, inserted: usize) -> bool {
+        let new_len = [left.len(), right.len()];
+        let mut old_len = new_len;
+        let Some(len) = (new_len[i] + old.len()).checked_sub(inserted) else {
+            return false;
+        };
+        old_len[i] = len;
+
+        // Where a line is in rows. Lines trimmed off with the context are between rows, so the edit takes in
+        // the kept rows either side of them: the first row after them for the end, the last one before for
+        // the start. Before the first row, the start is the top of both files
+        let after = |line: usize| self.row_of(i, line).unwrap_or_else(|| self.segments.iter()
+            .find(|s| s.side(i).is_some_and(|start| start > line))
+            .map_or(self.rows, |s| s.row));
+        let (mut start, from_top) = match self.row_of(i, old.start) {
+            Some(row) => (row, false),
+            None => after(old.start).checked_sub(1).map_or((0, true), |row| (row, false)),
+        };
+        let mut end = after(old.end);
+
+        // Widen out to whole hunks, including ones the edit only touches
+        if let Some(hunk) = self.hunk_at(start).or_else(|| self.hunk_at(start.checked_sub(1)?)) {
+            start = hunk.start;
+        }
+        if let Some(hunk) = self.hunk_at(end) {
+            end = hunk.end;
+        }
+
+        let line = |row: usize, side: usize| if row == self.rows { old_len[side] } else { self.line_at(row, side) };
+        let first = |side: usize| if from_top { 0 } else { line(start, side) };
+        let (l, r) = (first(0)..line(end, 0), first(1)..line(end, 1));
+        let delta = inserted as isize - old.len() as isize;
+        let (l, r) = match i {
+            0 => (l.start..l.end.wrapping_add_signed(delta), r),
+            _ => (l, r.start..r.end.wrapping_add_signed(delta)),
+        };
+        if l.end > new_len[0] || r.end > new_len[1] {
+            return false;
+        }
+
+        let mut region = Vec::new();
+        diff_gap(&mut region, left, right, cmp, algorithm, l, r);
+
+        let mut segments = self.slice(0..start);
+        for s in pair_changes(region).into_iter().chain(self.slice(end..self.rows).into_iter().map(|mut s| {
+            // Everything after the edit moved on the side that got edited
+            match i {
+                0 => s.left = s.left.wrapping_add_signed(delta),
+                _ => s.right = s.right.wrapping_add_signed(delta),
+            }
+            s
+        })) {
+            push(&mut segments, s.kind, s.left, s.right, s.len);
+        }
+        mark_ignored(&mut segments, left, right, cmp);
+
+        if let Some(context) = context {
+            segments = trim_context(segments, context);
+        }
+
+        *self = Self::from_segments(segments);
+        true
+    }
+
+    /// The segments covering `rows`, cut down to fit
+    fn slice(&self, rows: Range<usize>) -> Vec<Segment> {
+        self.segments.iter().filter_map(|s| {
+            let (from, to) = (std::cmp::max(s.row, rows.start), std::cmp::min(s.row + s.len, rows.end));
+            let offset = from.checked_sub(s.row)?;
+
+            (from < to).then(|| s.cut(offset..offset + to - from))
+        })
+        .collect::<Vec<Segment>>()
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows
+    }
+
+    pub fn row(&self, row: usize) -> Row {
+        let s = &self.segments[self.segments.partition_point(|s| s.row <= row) - 1];
+        let offset = row - s.row;
+
+        Row {
+            kind: s.kind,
+            left: (s.kind != Kind::Insert).then_some(s.left + offset),
+            right: (s.kind != Kind::Delete).then_some(s.right + offset),
+            ignored: s.ignored,
+        }
+    }
+
+    /// Whether segment `i` starts a run of changes
+    fn starts_hunk(&self, i: usize) -> bool {
+        self.segments[i].is_change() && (i == 0 || !self.segments[i - 1].is_change())
+    }
+
+    /// The first row of the next hunk after `row`
+    pub fn next_hunk(&self, row: usize) -> Option<usize> {
+        self.hunks.get(self.hunks.partition_point(|&h| h <= row)).copied()
+    }
+
+    /// The first row of the last hunk starting before `row`
+    pub fn prev_hunk(&self, row: usize) -> Option<usize> {
+        self.hunks.partition_point(|&h| h < row).checked_sub(1).map(|i| self.hunks[i])
+    }
+
+    pub fn hunk_count(&self) -> usize {
+        self.hunks.len()
+    }
+
+    /// How many hunks start at or before `row`, so the one `row` is in (or just past) counting from 1
+    pub fn hunk_index(&self, row: usize) -> usize {
+        self.hunks.partition_point(|&h| h <= row)
+    }
+
+    /// How many rows there are of each kind, as (inserted, deleted, changed)
+    pub fn stats(&self) -> (usize, usize, usize) {
+        self.segments.iter().filter(|s| s.is_change()).fold((0, 0, 0), |(i, d, c), s| match s.kind {
+            Kind::Insert => (i + s.len, d, c),
+            Kind::Delete => (i, d + s.len, c),
+            Kind::Change => (i, d, c + s.len),
+            Kind::Context => (i, d, c),
+        })
+    }
+
+    /// The rows of the hunk `row` is part of, if it's part of one
+    pub fn hunk_at(&self, row: usize) -> Option<Range<usize>> {
+        let i = self.segments.partition_point(|s| s.row <= row).checked_sub(1)?;
+        if row >= self.rows || !self.segments[i].is_change() {
+            return None;
+        }
+
+        let start = (0..=i).rev().find(|&j| self.starts_hunk(j))?;
+        let end = (i + 1..self.segments.len())
+            .find(|&j| !self.segments[j].is_change())
+            .map_or(self.rows, |j| self.segments[j].row);
+
+        Some(self.segments[start].row..end)
+    }
+
+    /// The line on side `i` at `row`. For filler rows that's the line that comes after them
+    pub fn line_at(&self, row: usize, i: usize) -> usize {
+        let s = &self.segments[self.segments.partition_point(|s| s.row <= row) - 1];
+        let offset = row - s.row;
+
+        match (i, s.kind) {
+            (0, Kind::Insert) => s.left,
+            (0, _) => s.left + offset,
+            (_, Kind::Delete) => s.right,
+            (_, _) => s.right + offset,
+        }
+    }
+
+    /// The row line `line` of side `i` is on, unless it got left out with the context
+    pub fn row_of(&self, i: usize, line: usize) -> Option<usize> {
+        // Segments without lines on this side still know where they'd go, which keeps this sorted
+        let first = self.segments.partition_point(|s| match s.side(i) {
+            Some(start) => start + s.len <= line,
+            None => if i == 0 { s.left <= line } else { s.right <= line },
+        });
+        let s = self.segments[first..].iter().find(|s| s.side(i).is_some())?;
+        let start = s.side(i)?;
+
+        (start..start + s.len).contains(&line).then(|| s.row + line - start)
+    }
+
+    /// The lines of side `i` that don't have a match on the other side
+    pub fn changed_lines(&self, i: usize) -> Vec<Range<usize>> {
+        self.segments.iter()
+            .filter(|s| s.is_change())
+            .filter_map(|s| s.side(i).map(|start| start..start + s.len))
+            .collect::<Vec<Range<usize>>>()
+    }
+
+    /// `rows` as a unified diff hunk, without the file names
+    pub fn patch(&self, rows: Range<usize>, left: &MappedFile, right: &MappedFile) -> String {
+        let start = rows.start;
+        let rows = self.rows(rows.start, rows.len()).collect::<Vec<Row>>();
+        if rows.is_empty() {
+            return String::new();
+        }
+
+        // A side with no lines gets numbered by the line before it, like diff -u does
+        let counts = [0, 1].map(|i| rows.iter().filter(|r| r.side(i).is_some()).count());
+        let starts = [0, 1].map(|i| self.line_at(start, i) + (counts[i] > 0) as usize);
+        let mut patch = format!("@@ -{},{} +{},{} @@\n", starts[0], counts[0], starts[1], counts[1]);
+
+        let mut push_line = |prefix: char, file: &MappedFile, n: usize| {
+            let text = file.text(n..n + 1);
+            patch.push(prefix);
+            patch.push_str(&text);
+            if !text.ends_with('\n') {
+                patch.push_str("\n\\ No newline at end of file\n");
+            }
+        };
+
+        // Each run of changes lists everything removed before everything added
+        for run in rows.chunk_by(|a, b| (a.kind == Kind::Context) == (b.kind == Kind::Context)) {
+            if run[0].kind == Kind::Context {
+                run.iter().filter_map(|r| r.left).for_each(|n| push_line(' ', left, n));
+            } else {
+                run.iter().filter_map(|r| r.left).for_each(|n| push_line('-', left, n));
+                run.iter().filter_map(|r| r.right).for_each(|n| push_line('+', right, n));
+            }
+        }
+
+        patch
+    }
+
+    /// What kind of changes there are in `rows`. Deletions and insertions together count as a change,
+    /// and `Context` means there aren't any
+    pub fn summary(&self, rows: Range<usize>) -> Kind {
+        let first = self.segments.partition_point(|s| s.row <= rows.start).saturating_sub(1);
+        let mut kind = Kind::Context;
+
+        for s in self.segments[first..].iter().take_while(|s| s.row < rows.end).filter(|s| s.is_change()) {
+            kind = match (kind, s.kind) {
+                (k, Kind::Context) | (Kind::Context, k) => k,
+                (a, b) if a == b => a,
+                _ => return Kind::Change
+            };
+        }
+
+        kind
+    }
+
+    /// Up to `count` rows starting at `start`
+    pub fn rows(&self, start: usize, count: usize) -> impl Iterator<Item = Row> + '_ {
+        (start..std::cmp::min(start.saturating_add(count), self.rows)).map(|i| self.row(i))
+    }
+}
+
+fn push(segments: &mut Vec<Segment>, kind: Kind, left: usize, right: usize, len: usize) {
+    push_segment(segments, Segment { kind, row: 0, left, right, len, ignored: false });
+}
+
+/// Add a segment, joining it onto the last one if it carries straight on from it
+fn push_segment(segments: &mut Vec<Segment>, s: Segment) {
+    if s.len == 0 {
+        return;
+    }
+
+    if let Some(last) = segments.last_mut() {
+        let left_end = last.left + if last.kind == Kind::Insert { 0 } else { last.len };
+        let right_end = last.right + if last.kind == Kind::Delete { 0 } else { last.len };
+
+        if last.kind == s.kind && last.ignored == s.ignored && left_end == s.left && right_end == s.right {
+            last.len += s.len;
+            return;
+        }
+    }
+
+    segments.push(Segment { row: 0, ..s });
+}
+
+/// Flag the hunks where every line on both sides is one the comparator ignores
+fn mark_ignored(segments: &mut [Segment], left: &MappedFile, right: &MappedFile, cmp: &Comparator) {
+    if !cmp.ignores_lines() {
+        return;
+    }
+
+    for run in segments.chunk_by_mut(|a, b| (a.kind == Kind::Context) == (b.kind == Kind::Context)) {
+        if run[0].kind == Kind::Context {
+            continue;
+        }
+
+        let ignored = run.iter().all(|s| [(0, left), (1, right)].into_iter().all(|(i, file)| match s.side(i) {
+            Some(start) => file.lines_from(start).take(s.len).all(|line| cmp.ignores(line)),
+            None => true
+        }));
+        for s in run {
+            s.ignored = ignored;
+        }
+    }
+}
+
+/// Pairs of indexes of lines that are unique in both windows, in increasing order on both sides
+fn unique_anchors(left: &[&[u8]], right: &[&[u8]], cmp: &Comparator) -> Vec<(usize, usize)> {
+    let mut counts: HashMap<Cow<[u8]>, (usize, usize, usize)> = HashMap::with_capacity(left.len());
+    for (i, &line) in left.iter().enumerate() {
+        let e = counts.entry(cmp.key(line)).or_insert((0, i, 0));
+        e.0 += 1;
+    }
+    for (j, &line) in right.iter().enumerate() {
+        if let Some(e) = counts.get_mut(&cmp.key(line)) {
+            e.2 = if e.2 == 0 { j + 1 } else { usize::MAX };
+        }
+    }
+
+    let mut pairs = counts.into_values()
+        .filter(|&(count, _, j)| count == 1 && j != 0 && j != usize::MAX)
+        .map(|(_, i, j)| (i, j - 1))
+        .collect::<Vec<(usize, usize)>>();
+    pairs.sort_unstable();
+
+    longest_increasing(&pairs)
+}
+
+/// Diff the lines between two anchors. The algorithm sees each line as a number, the same for lines with the same key
+#[allow(clippy::too_many_arguments)]
+fn diff_gap(segments: &mut Vec<Segment>, left: &MappedFile, right: &MappedFile, cmp: &Comparator, algorithm: Algorithm, l: Range<usize>, r: Range<usize>) {
+    if l.is_empty() || r.is_empty() {
+        push(segments, Kind::Delete, l.start, r.start, l.len());
+        push(segments, Kind::Insert, l.end, r.start, r.len());
+        return;
+    }
+
+    let mut ids: HashMap<Cow<[u8]>, usize> = HashMap::new();
+    let mut id = |line| {
+        let next = ids.len();
+        *ids.entry(cmp.key(line)).or_insert(next)
+    };
+    let old = left.lines_from(l.start).take(l.len()).map(&mut id).collect::<Vec<usize>>();
+    let new = right.lines_from(r.start).take(r.len()).map(&mut id).collect::<Vec<usize>>();
+
+    // Differs can hand back deletions and insertions in any order between equal lines, so put the
+    // deletions first, the way pair_changes expects
+    let mut ops = algorithm.differ().diff(&old, &new);
+    for run in ops.split_mut(|op| *op == Op::Equal) {
+        run.sort_unstable_by_key(|op| *op == Op::Insert);
+    }
+
+    let (mut i, mut j) = (l.start, r.start);
+    for op in ops {
+        match op {
+            Op::Equal => {
+                push(segments, Kind::Context, i, j, 1);
+                i += 1;
+                j += 1;
+            },
+            Op::Delete => {
+                push(segments, Kind::Delete, i, j, 1);
+                i += 1;
+            },
+            Op::Insert => {
+                push(segments, Kind::Insert, i, j, 1);
+                j += 1;
+            }
+        }
+    }
+}
+
+/// Line up deletions with the insertions right after them, like a side-by-side diff should
+fn pair_changes(segments: Vec<Segment>) -> Vec<Segment> {
+    let mut paired: Vec<Segment> = Vec::with_capacity(segments.len());
+    let mut segments = segments.into_iter().peekable();
+
+    while let Some(s) = segments.next() {
+        let next = segments.peek().copied();
+        match next {
+            Some(n) if s.kind == Kind::Delete && n.kind == Kind::Insert => {
+                segments.next();
+                let len = std::cmp::min(s.len, n.len);
+                push(&mut paired, Kind::Change, s.left, n.right, len);
+                push(&mut paired, Kind::Delete, s.left + len, n.right + len, s.len - len);
+                push(&mut paired, Kind::Insert, s.left + s.len, n.right + len, n.len - len);
+            },
+            _ => push(&mut paired, s.kind, s.left, s.right, s.len)
+        }
+    }
+
+    paired
+}
+
+/// Drop unchanged lines further than `context` lines away from a change. Ignored hunks count as unchanged
+fn trim_context(segments: Vec<Segment>, context: usize) -> Vec<Segment> {
+    let runs = segments.chunk_by(|a, b| a.is_change() == b.is_change()).collect::<Vec<&[Segment]>>();
+    let last = runs.len().saturating_sub(1);
+    let mut trimmed: Vec<Segment> = Vec::with_capacity(segments.len());
+
+    for (idx, run) in runs.into_iter().enumerate() {
+        let len = run.iter().map(|s| s.len).sum::<usize>();
+        let head = if idx == 0 { 0 } else { context };
+        let tail = if idx == last { 0 } else { context };
+
+        if run[0].is_change() || len <= head + tail {
+            run.iter().for_each(|&s| push_segment(&mut trimmed, s));
+            continue;
+        }
+
+        // Keep the first `head` rows and the last `tail` rows of the run
+        let mut offset = 0;
+        for s in run {
+            for keep in [0..head, len - tail..len] {
+                let (from, to) = (std::cmp::max(keep.start, offset), std::cmp::min(keep.end, offset + s.len));
+                if from < to {
+                    push_segment(&mut trimmed, s.cut(from - offset..to - offset));
+                }
+            }
+            offset += s.len;
+        }
+    }
+
+    trimmed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compare::Whitespace;
+
+    // A tiny xorshift so the tests don't need a crate for random numbers, and always see the same inputs
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+
+        // Few distinct values, so there's plenty to line up and plenty of ways to do it
+        fn ids(&mut self, max_len: usize) -> Vec<usize> {
+            let len = self.below(max_len + 1);
+            (0..len).map(|_| self.below(4)).collect()
+        }
+    }
+
+    fn file(ids: &[usize]) -> MappedFile {
+        MappedFile::from_bytes(ids.iter().map(|id| format!("line {}\n", id)).collect::<String>().into_bytes())
+    }
+
+    /// Check the rows go through both files in order, with matching lines on unchanged rows, and that
+    /// looking rows up by line and finding hunks agree with them
+    fn check_rows(diff: &Diff, left: &[usize], right: &[usize]) {
+        let rows = diff.rows(0, diff.len()).collect::<Vec<Row>>();
+        let mut next = [0, 0];
+        for (n, row) in rows.iter().enumerate() {
+            for (i, side) in [left, right].into_iter().enumerate() {
+                if let Some(line) = row.side(i) {
+                    assert_eq!(line, next[i], "side {} skips or repeats a line", i);
+                    assert!(line < side.len());
+                    assert_eq!(diff.row_of(i, line), Some(n));
+                    next[i] += 1;
+                }
+            }
+            if row.kind == Kind::Context {
+                assert_eq!(left[row.left.unwrap()], right[row.right.unwrap()], "unchanged row with different lines");
+            }
+        }
+        assert_eq!(next, [left.len(), right.len()], "rows don't cover both files");
+
+        let starts = (0..rows.len())
+            .filter(|&n| rows[n].kind != Kind::Context && (n == 0 || rows[n - 1].kind == Kind::Context))
+            .collect::<Vec<usize>>();
+        let hunks = (0..rows.len()).filter(|&n| diff.prev_hunk(n + 1) == Some(n)).collect::<Vec<usize>>();
+        assert_eq!(hunks, starts, "hunks don't match the rows");
+        assert_eq!(diff.hunk_count(), starts.len());
+    }
+
+    /// Replace lines `old` of `right` with a few random ones, returning the edit
+    fn edit(rng: &mut Rng, right: &mut Vec<usize>) -> (Range<usize>, usize) {
+        let start = rng.below(right.len() + 1);
+        let old = start..start + rng.below(right.len() - start + 1);
+        let inserted = rng.ids(3);
+        right.splice(old.clone(), inserted.iter().copied());
+        (old, inserted.len())
+    }
+
+    // Updates leave the rows away from the edit alone, so they can line up differently than a fresh
+    // diff would. They still have to be a proper diff of the new files
+    #[test]
+    fn update_keeps_the_rows_consistent() {
+        let cmp = Comparator::new(Whitespace::Exact, Vec::new(), false, Vec::new());
+        let mut rng = Rng(0x9e3779b97f4a7c15);
+        for _ in 0..2000 {
+            let (left, mut right) = (rng.ids(20), rng.ids(20));
+            let algorithm = Algorithm::ALL[rng.below(Algorithm::ALL.len())];
+            let mut diff = Diff::compute(&file(&left), &file(&right), &cmp, algorithm, None, &Progress::default()).unwrap();
+            check_rows(&diff, &left, &right);
+
+            let (old, inserted) = edit(&mut rng, &mut right);
+            if diff.update(&file(&left), &file(&right), &cmp, algorithm, None, 1, old, inserted) {
+                check_rows(&diff, &left, &right);
+            }
+        }
+    }
+
+    // With nothing else changed, the edit is all there is to diff either way. A deletion can still end up
+    // on either side of lines equal to it, so compare how much changed instead of the exact rows
+    #[test]
+    fn update_matches_recompute() {
+        let cmp = Comparator::new(Whitespace::Exact, Vec::new(), false, Vec::new());
+        let mut rng = Rng(0xd1b54a32d192ed03);
+        for _ in 0..2000 {
+            let left = rng.ids(20);
+            let mut right = left.clone();
+            let algorithm = Algorithm::ALL[rng.below(Algorithm::ALL.len())];
+            let mut diff = Diff::compute(&file(&left), &file(&right), &cmp, algorithm, None, &Progress::default()).unwrap();
+
+            let (old, inserted) = edit(&mut rng, &mut right);
+            let (l, r) = (file(&left), file(&right));
+            assert!(diff.update(&l, &r, &cmp, algorithm, None, 1, old, inserted));
+
+            let recomputed = Diff::compute(&l, &r, &cmp, algorithm, None, &Progress::default()).unwrap();
+            assert_eq!(diff.stats(), recomputed.stats(), "{:?} update went differently for {:?} -> {:?}", algorithm, left, right);
+        }
+    }
+
+    /// Like `check_rows`, for a diff with its context trimmed: lines left off between segments have to be
+    /// the same on both sides
+    fn check_trimmed(diff: &Diff, left: &[usize], right: &[usize]) {
+        let (mut next, mut row) = ([0, 0], 0);
+        let skip = |next: &mut [usize; 2], to: [usize; 2]| {
+            assert_eq!(to[0] - next[0], to[1] - next[1], "different amounts trimmed from each side");
+            assert_eq!(left[next[0]..to[0]], right[next[1]..to[1]], "trimmed lines that changed");
+            *next = to;
+        };
+        for segment in &diff.segments {
+            assert_eq!(segment.row, row, "segments skip or repeat a row");
+            skip(&mut next, [segment.left, segment.right]);
+            if segment.kind == Kind::Context {
+                assert_eq!(left[next[0]..next[0] + segment.len], right[next[1]..next[1] + segment.len], "unchanged rows with different lines");
+            }
+            for (i, next) in next.iter_mut().enumerate() {
+                if segment.side(i).is_some() {
+                    *next += segment.len;
+                }
+            }
+            row += segment.len;
+        }
+        assert_eq!(row, diff.len());
+        skip(&mut next, [left.len(), right.len()]);
+    }
+
+    // Edits in or next to trimmed context used to make `update` give up and leave it to a full recompute
+    #[test]
+    fn update_reaches_into_trimmed_context() {
+        let cmp = Comparator::new(Whitespace::Exact, Vec::new(), false, Vec::new());
+        let left = (0..10).collect::<Vec<usize>>();
+        let mut right = left.clone();
+        right[3] = 20;
+        let mut diff = Diff::compute(&file(&left), &file(&right), &cmp, Algorithm::Myers, Some(1), &Progress::default()).unwrap();
+
+        // The last context line after the change, with the line after it trimmed
+        right[4] = 21;
+        assert!(diff.update(&file(&left), &file(&right), &cmp, Algorithm::Myers, Some(1), 1, 4..5, 1));
+        check_trimmed(&diff, &left, &right);
+        assert_eq!(diff.stats(), (0, 0, 2));
+
+        let mut rng = Rng(0x2545f4914f6cdd1d);
+        for _ in 0..2000 {
+            let left = (0..rng.below(40)).map(|_| rng.below(4)).collect::<Vec<usize>>();
+            let mut right = left.clone();
+            if !right.is_empty() {
+                let n = rng.below(right.len());
+                right[n] = 4;
+            }
+            let algorithm = Algorithm::ALL[rng.below(Algorithm::ALL.len())];
+            let context = Some(rng.below(3));
+            let mut diff = Diff::compute(&file(&left), &file(&right), &cmp, algorithm, context, &Progress::default()).unwrap();
+            check_trimmed(&diff, &left, &right);
+
+            let (old, inserted) = edit(&mut rng, &mut right);
+            assert!(diff.update(&file(&left), &file(&right), &cmp, algorithm, context, 1, old, inserted), "{:?} -> {:?}", left, right);
+            check_trimmed(&diff, &left, &right);
+        }
+    }
+
+    // An insertion longer than a window used to push every window after it out of step
+    #[test]
+    fn long_insertion_stays_lined_up() {
+        let cmp = Comparator::new(Whitespace::Exact, Vec::new(), false, Vec::new());
+        let left = (0..30_000).map(|n| format!("line {}\n", n)).collect::<Vec<String>>();
+        let mut right = left.clone();
+        right[25_000] = String::from("edited\n");
+        right.splice(1000..1000, (0..10_000).map(|n| format!("inserted {}\n", n)));
+
+        let file = |lines: &[String]| MappedFile::from_bytes(lines.concat().into_bytes());
+        let diff = Diff::compute(&file(&left), &file(&right), &cmp, Algorithm::Myers, None, &Progress::default()).unwrap();
+        assert_eq!(diff.stats(), (10_000, 0, 1));
+    }
+}
@@ -0,0 +1,246 @@
+use std::{fs, io, ops::Range, path::Path};
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::input::MappedFile;
+
+/// Lines `start..start + removed` of the file got replaced with `inserted` lines
+#[derive(Clone, Copy, Debug)]
+pub struct Change {
+    pub start: usize,
+    pub removed: usize,
+    pub inserted: usize,
+}
+
+/// One step of the undo history: the lines that were there before it, and where the cursor was
+struct Edit {
+    start: usize,
+    removed: Vec<String>,
+    inserted: usize,
+    cursor: (usize, usize),
+}
+
+/// One of the files being edited, kept as lines so edits don't shuffle the whole file around
+pub struct Editor {
+    pub side: usize,
+    lines: Vec<String>,
+    // So saving doesn't add or drop the newline at the end of the file
+    newline_at_end: bool,
+    /// The line and character the cursor is on
+    pub cursor: (usize, usize),
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+    // Whether the last undo step is typing on the cursor's line, so more typing can join it
+    typing: bool,
+    pub dirty: bool,
+}
+
+impl Editor {
+    /// Start editing side `side`, which is `file`, with the cursor on `line`
+    pub fn new(side: usize, file: &MappedFile, line: usize) -> Result<Self, String> {
+        let bytes = file.bytes();
+        let text = std::str::from_utf8(&bytes).map_err(|_| String::from("can't edit a file that isn't UTF-8"))?;
+
+        let newline_at_end = text.ends_with('\n');
+        let mut lines = text.split('\n').map(String::from).collect::<Vec<String>>();
+        if newline_at_end {
+            lines.pop();
+        }
+        if lines.is_empty() {
+            lines.push(String::new());
+        }
+
+        Ok(Self {
+            side,
+            cursor: (std::cmp::min(line, lines.len() - 1), 0),
+            lines,
+            newline_at_end,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            typing: false,
+            dirty: false,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// The file as it is now, to diff against
+    pub fn file(&self) -> MappedFile {
+        MappedFile::from_lines(self.lines.clone(), self.newline_at_end)
+    }
+
+    /// Lines `range` as they are now
+    pub fn lines(&self, range: Range<usize>) -> &[String] {
+        &self.lines[range]
+    }
+
+    /// The text of the file as it would be saved
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut text = self.lines.join("\n");
+        if self.newline_at_end {
+            text.push('\n');
+        }
+        text.into_bytes()
+    }
+
+    pub fn save<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        fs::write(path, self.bytes())?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// How far into the line the cursor is once tabs are expanded, the way the line is shown
+    pub fn display_col(&self, tab_width: usize) -> usize {
+        let (line, col) = self.cursor;
+        self.lines[line].chars().take(col).map(|c| if c == '\t' { tab_width } else { 1 }).sum()
+    }
+
+    /// Handle a key. Returns what changed in the text, if anything did
+    pub fn key(&mut self, key: &KeyEvent) -> Option<Change> {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+        let (line, col) = self.cursor;
+        let width = self.lines[line].chars().count();
+        let typing = std::mem::replace(&mut self.typing, false);
+
+        match key.code {
+            KeyCode::Char('z') if ctrl => return self.undo(),
+            KeyCode::Char('y') if ctrl => return self.redo(),
+            // Line operations: delete, duplicate, and move up and down
+            KeyCode::Char('k') if ctrl => {
+                let change = match self.lines.len() {
+                    1 => self.replace(0, 1, vec![String::new()]),
+                    _ => self.replace(line, 1, Vec::new()),
+                };
+                self.cursor = (std::cmp::min(line, self.lines.len() - 1), 0);
+                return Some(change);
+            },
+            KeyCode::Char('d') if ctrl => {
+                let text = self.lines[line].clone();
+                let change = self.replace(line, 1, vec![text.clone(), text]);
+                self.cursor = (line + 1, col);
+                return Some(change);
+            },
+            KeyCode::Up if alt && line > 0 => {
+                let change = self.replace(line - 1, 2, vec![self.lines[line].clone(), self.lines[line - 1].clone()]);
+                self.cursor = (line - 1, col);
+                return Some(change);
+            },
+            KeyCode::Down if alt && line + 1 < self.lines.len() => {
+                let change = self.replace(line, 2, vec![self.lines[line + 1].clone(), self.lines[line].clone()]);
+                self.cursor = (line + 1, col);
+                return Some(change);
+            },
+            KeyCode::Char(c) if !ctrl && !alt => return Some(self.type_char(c, typing)),
+            KeyCode::Tab => return Some(self.type_char('\t', typing)),
+            KeyCode::Enter => {
+                let (before, after) = self.split_at_cursor();
+                let change = self.replace(line, 1, vec![before, after]);
+                self.cursor = (line + 1, 0);
+                return Some(change);
+            },
+            KeyCode::Backspace if col > 0 => {
+                let mut text = self.lines[line].clone();
+                text.remove(byte_index(&text, col - 1));
+                self.cursor = (line, col - 1);
+                return Some(self.set_line(line, text, typing));
+            },
+            KeyCode::Backspace if line > 0 => {
+                let joined = self.lines[line - 1].clone() + &self.lines[line];
+                self.cursor = (line - 1, self.lines[line - 1].chars().count());
+                return Some(self.replace(line - 1, 2, vec![joined]));
+            },
+            KeyCode::Delete if col < width => {
+                let mut text = self.lines[line].clone();
+                text.remove(byte_index(&text, col));
+                return Some(self.set_line(line, text, typing));
+            },
+            KeyCode::Delete if line + 1 < self.lines.len() => {
+                let joined = self.lines[line].clone() + &self.lines[line + 1];
+                return Some(self.replace(line, 2, vec![joined]));
+            },
+            KeyCode::Left if col > 0 => self.cursor = (line, col - 1),
+            KeyCode::Left if line > 0 => self.cursor = (line - 1, self.lines[line - 1].chars().count()),
+            KeyCode::Right if col < width => self.cursor = (line, col + 1),
+            KeyCode::Right if line + 1 < self.lines.len() => self.cursor = (line + 1, 0),
+            KeyCode::Up if line > 0 => self.cursor = (line - 1, std::cmp::min(col, self.lines[line - 1].chars().count())),
+            KeyCode::Down if line + 1 < self.lines.len() => self.cursor = (line + 1, std::cmp::min(col, self.lines[line + 1].chars().count())),
+            KeyCode::Home => self.cursor = (line, 0),
+            KeyCode::End => self.cursor = (line, width),
+            _ => {}
+        }
+
+        None
+    }
+
+    fn type_char(&mut self, c: char, typing: bool) -> Change {
+        let (line, col) = self.cursor;
+        let mut text = self.lines[line].clone();
+        text.insert(byte_index(&text, col), c);
+        self.cursor = (line, col + 1);
+        self.set_line(line, text, typing)
+    }
+
+    fn split_at_cursor(&self) -> (String, String) {
+        let (line, col) = self.cursor;
+        let (before, after) = self.lines[line].split_at(byte_index(&self.lines[line], col));
+        (before.to_string(), after.to_string())
+    }
+
+    /// Change one line. Keeps adding to the last undo step while typing on the same line
+    fn set_line(&mut self, line: usize, text: String, typing: bool) -> Change {
+        self.typing = true;
+        let joins = typing && self.undo.last().is_some_and(|e| e.start == line && e.inserted == 1 && e.removed.len() == 1);
+
+        if !joins {
+            return self.replace(line, 1, vec![text]);
+        }
+
+        self.lines[line] = text;
+        self.dirty = true;
+        Change { start: line, removed: 1, inserted: 1 }
+    }
+
+    fn replace(&mut self, start: usize, count: usize, lines: Vec<String>) -> Change {
+        let inserted = lines.len();
+        let removed = self.lines.splice(start..start + count, lines).collect::<Vec<String>>();
+
+        self.undo.push(Edit { start, removed, inserted, cursor: self.cursor });
+        self.redo.clear();
+        self.dirty = true;
+
+        Change { start, removed: count, inserted }
+    }
+
+    fn undo(&mut self) -> Option<Change> {
+        let edit = self.undo.pop()?;
+        let (change, edit) = self.revert(edit);
+        self.redo.push(edit);
+        Some(change)
+    }
+
+    fn redo(&mut self) -> Option<Change> {
+        let edit = self.redo.pop()?;
+        let (change, edit) = self.revert(edit);
+        self.undo.push(edit);
+        Some(change)
+    }
+
+    /// Put back what an edit replaced. Gives back the change, and the edit that would undo this
+    fn revert(&mut self, edit: Edit) -> (Change, Edit) {
+        let restored = edit.removed.len();
+        let removed = self.lines.splice(edit.start..edit.start + edit.inserted, edit.removed).collect::<Vec<String>>();
+
+        let opposite = Edit { start: edit.start, removed, inserted: restored, cursor: self.cursor };
+        self.cursor = edit.cursor;
+        self.dirty = true;
+
+        (Change { start: edit.start, removed: edit.inserted, inserted: restored }, opposite)
+    }
+}
+
+/// Where character `col` starts in `text`, or the end if it's past the last one
+fn byte_index(text: &str, col: usize) -> usize {
+    text.char_indices().nth(col).map_or(text.len(), |(i, _)| i)
+}
@@ -0,0 +1,203 @@
+use std::{collections::HashMap, error::Error, ops::Range, path::Path};
+use syntect::{highlighting::{HighlightIterator, HighlightState, Highlighter, Style, Theme}, parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet}};
+
+// How many lines apart the saved parser states are. Jumping anywhere only replays at most this many lines
+const CHECKPOINT_STRIDE: usize = 500;
+
+// Replaying further than this to reach a far away line would stall the UI, so start from a fresh
+// state a little before it instead. Multi-line constructs can come out wrong until the checkpoints,
+// which move this much further along every time, get there properly
+const MAX_REPLAY: usize = 20 * CHECKPOINT_STRIDE;
+
+// Drop the cached lines once there are this many so scrolling through a huge file doesn't keep all of it
+const CACHE_LIMIT: usize = 10_000;
+
+pub type StyledLine = Vec<(Style, String)>;
+
+/// Pick the syntax for a file. `name` is a user override, otherwise go by the file name and then the
+/// first line. `None` means plain text
+pub fn find_syntax<'a>(ps: &'a SyntaxSet, name: Option<&str>, path: &str, first_line: &str) -> Result<Option<&'a SyntaxReference>, Box<dyn Error>> {
+    let syntax = match name {
+        Some(name) => Some(
+            ps.find_syntax_by_name(name)
+                .or_else(|| ps.find_syntax_by_token(name))
+                .ok_or_else(|| format!("Unknown syntax {}", name))?
+        ),
+        None => {
+            let path = Path::new(path);
+            let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+
+            ps.find_syntax_by_extension(file_name)
+                .or_else(|| ps.find_syntax_by_extension(extension))
+                .or_else(|| ps.find_syntax_by_first_line(first_line))
+                .or_else(|| shebang_interpreter(first_line).and_then(|i| ps.find_syntax_by_token(&i)))
+        }
+    };
+
+    Ok(syntax.filter(|s| s.name != ps.find_syntax_plain_text().name))
+}
+
+/// The interpreter a `#!` line runs, e.g. `python` for `#!/usr/bin/env -S python3 -u`
+fn shebang_interpreter(first_line: &str) -> Option<String> {
+    let mut words = first_line.strip_prefix("#!")?.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+
+    // Skip over env and its flags to whatever it runs
+    if program == "env" {
+        program = words.find(|w| !w.starts_with('-') && !w.contains('='))?;
+    }
+
+    // python3.12 -> python
+    let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    Some(String::from(match program {
+        "node" | "nodejs" | "deno" | "bun" => "js",
+        "sh" | "zsh" | "ksh" | "dash" | "ash" => "bash",
+        other => other
+    }))
+}
+
+/// Highlights one file on demand, remembering where it has already been
+pub struct LazyHighlighter<'a> {
+    syntax: Option<&'a SyntaxReference>,
+    highlighter: Highlighter<'a>,
+    ps: &'a SyntaxSet,
+    // The parser state at the start of every CHECKPOINT_STRIDE'th line
+    checkpoints: Vec<(ParseState, HighlightState)>,
+    cache: HashMap<usize, StyledLine>,
+    // Lines highlighted from a fresh state, only shown until they can be done properly
+    guessed: HashMap<usize, StyledLine>,
+}
+
+impl<'a> LazyHighlighter<'a> {
+    pub fn new(syntax: Option<&'a SyntaxReference>, theme: &'a Theme, ps: &'a SyntaxSet) -> Self {
+        let highlighter = Highlighter::new(theme);
+        let checkpoints = match syntax {
+            Some(syntax) => vec![(ParseState::new(syntax), HighlightState::new(&highlighter, ScopeStack::new()))],
+            None => Vec::new(),
+        };
+
+        Self {
+            syntax,
+            highlighter,
+            ps,
+            checkpoints,
+            cache: HashMap::new(),
+            guessed: HashMap::new(),
+        }
+    }
+
+    /// Highlight lines of the file, given in increasing order. `lines_from(n)` has to iterate over the file starting at line `n`
+    pub fn highlight<I, F>(&mut self, lines: &[usize], lines_from: F) -> Vec<StyledLine>
+    where
+        I: Iterator<Item = String>,
+        F: Fn(usize) -> I,
+    {
+        // Nothing to keep track of for plain text
+        if self.syntax.is_none() {
+            return lines.iter()
+                .map(|&n| vec![(Style::default(), lines_from(n).next().unwrap_or_default())])
+                .collect::<Vec<StyledLine>>();
+        }
+
+        if self.cache.len() + self.guessed.len() > CACHE_LIMIT {
+            self.cache.clear();
+            self.guessed.clear();
+        }
+
+        // Lines close together get highlighted in one pass, anything between them is needed anyway
+        let missing = lines.iter().copied().filter(|n| !self.cache.contains_key(n)).collect::<Vec<usize>>();
+        for run in missing.chunk_by(|a, b| b - a <= CHECKPOINT_STRIDE) {
+            self.fill(run[0]..run[run.len() - 1] + 1, &lines_from);
+        }
+
+        lines.iter()
+            .map(|n| self.cache.get(n).or_else(|| self.guessed.get(n)).cloned().unwrap_or_default())
+            .collect::<Vec<StyledLine>>()
+    }
+
+    /// Forget everything from line `line` on, after the file changed there
+    pub fn invalidate(&mut self, line: usize) {
+        self.cache.retain(|&n, _| n < line);
+        self.guessed.retain(|&n, _| n < line);
+        // The checkpoint at the start of a line is still good if nothing before the line changed
+        self.checkpoints.truncate(line / CHECKPOINT_STRIDE + 1);
+    }
+
+    /// Replay from the closest checkpoint before `range`, saving new checkpoints on the way
+    fn fill<I, F>(&mut self, range: Range<usize>, lines_from: &F)
+    where
+        I: Iterator<Item = String>,
+        F: Fn(usize) -> I,
+    {
+        if range.start.saturating_sub((self.checkpoints.len() - 1) * CHECKPOINT_STRIDE) > MAX_REPLAY {
+            self.advance(lines_from);
+        }
+
+        let k = std::cmp::min(range.start / CHECKPOINT_STRIDE, self.checkpoints.len() - 1);
+        let mut start = k * CHECKPOINT_STRIDE;
+        let (mut parse_state, mut highlight_state) = self.checkpoints[k].clone();
+
+        let exact = range.start - start <= MAX_REPLAY;
+        if !exact {
+            // Guesses don't get any better by doing them again
+            if range.clone().all(|n| self.guessed.contains_key(&n)) {
+                return;
+            }
+            start = range.start - CHECKPOINT_STRIDE;
+            parse_state = ParseState::new(self.syntax.unwrap());
+            highlight_state = HighlightState::new(&self.highlighter, ScopeStack::new());
+        }
+
+        for (n, line) in (start..range.end).zip(lines_from(start)) {
+            if exact && n % CHECKPOINT_STRIDE == 0 && n / CHECKPOINT_STRIDE == self.checkpoints.len() {
+                self.checkpoints.push((parse_state.clone(), highlight_state.clone()));
+            }
+
+            // The syntaxes are the newline flavour, so line comments and the like need to see where the line ends
+            let line = line + "\n";
+            let ops = parse_state.parse_line(&line, self.ps).unwrap_or_default();
+            let styled = HighlightIterator::new(&mut highlight_state, &ops, &line, &self.highlighter);
+
+            // Lines before the range only need to move the state along
+            if n >= range.start {
+                let styled = styled
+                    .map(|(style, s)| (style, s.trim_end_matches('\n').to_string()))
+                    .filter(|(_, s)| !s.is_empty())
+                    .collect::<StyledLine>();
+                match exact {
+                    true => {
+                        self.guessed.remove(&n);
+                        self.cache.insert(n, styled);
+                    },
+                    false => {
+                        self.guessed.insert(n, styled);
+                    },
+                }
+            } else {
+                styled.for_each(drop);
+            }
+        }
+    }
+
+    /// Replay up to MAX_REPLAY lines on from the last checkpoint, so lines too far away to be done
+    /// properly get closer each time they're asked for
+    fn advance<I, F>(&mut self, lines_from: &F)
+    where
+        I: Iterator<Item = String>,
+        F: Fn(usize) -> I,
+    {
+        let start = (self.checkpoints.len() - 1) * CHECKPOINT_STRIDE;
+        let (mut parse_state, mut highlight_state) = self.checkpoints[self.checkpoints.len() - 1].clone();
+
+        for (n, line) in (start..start + MAX_REPLAY).zip(lines_from(start)) {
+            let line = line + "\n";
+            let ops = parse_state.parse_line(&line, self.ps).unwrap_or_default();
+            HighlightIterator::new(&mut highlight_state, &ops, &line, &self.highlighter).for_each(drop);
+
+            if (n + 1) % CHECKPOINT_STRIDE == 0 {
+                self.checkpoints.push((parse_state.clone(), highlight_state.clone()));
+            }
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::{borrow::Cow, fs::{self, File}, io::{self, Read}, ops::Range, path::Path};
+use memmap2::Mmap;
+
+// Only every 64th line start is stored. Finding any other line scans forward from the
+// closest one, which keeps the index tiny even for multi-GB files
+const CHECKPOINT_STRIDE: usize = 64;
+
+enum Contents {
+    Empty,
+    Mapped(Mmap),
+    Read(Vec<u8>),
+    // A file being edited, kept as lines so an edit only has to touch the lines it changed
+    Lines(Vec<String>, bool),
+}
+
+/// A read-only file, usually memory-mapped, with a sparse index of its line starts
+pub struct MappedFile {
+    contents: Contents,
+    checkpoints: Vec<usize>,
+    line_count: usize,
+}
+
+impl MappedFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let metadata = file.metadata()?;
+
+        // Pipes and process substitutions like <(cmd) say they're empty and can't be mapped, so read
+        // them in. Mapping a zero-length file fails on some platforms, so don't bother
+        let contents = if !metadata.is_file() {
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            Contents::Read(bytes)
+        } else if metadata.len() == 0 {
+            Contents::Empty
+        } else {
+            // Safety: the map is never written through. If another process truncates the
+            // file while we have it open reads can fault, like any other mmap-based tool
+            Contents::Mapped(unsafe { Mmap::map(&file)? })
+        };
+
+        Ok(Self::index(contents))
+    }
+
+    /// Read the whole file into memory instead of mapping it, for files that are expected to
+    /// change while we have them open
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::index(Contents::Read(fs::read(path)?)))
+    }
+
+    /// A file that only exists in memory, like one being edited
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self::index(Contents::Read(bytes))
+    }
+
+    /// Copy a mapped file into memory, so it stays readable whatever happens to it on disk
+    pub fn unmap(&mut self) {
+        if let Contents::Mapped(map) = &self.contents {
+            self.contents = Contents::Read(map.to_vec());
+        }
+    }
+
+    /// A file being edited, as its lines and whether the last one ends in a newline
+    pub fn from_lines(lines: Vec<String>, newline_at_end: bool) -> Self {
+        let mut file = Self { contents: Contents::Lines(lines, newline_at_end), checkpoints: Vec::new(), line_count: 0 };
+        file.count_lines();
+        file
+    }
+
+    /// Replace lines `old` with `new`, for a file made from lines. Returns false for any other file
+    pub fn splice(&mut self, old: Range<usize>, new: &[String]) -> bool {
+        let Contents::Lines(lines, _) = &mut self.contents else {
+            return false;
+        };
+        if old.end > lines.len() {
+            return false;
+        }
+
+        lines.splice(old, new.iter().cloned());
+        self.count_lines();
+        true
+    }
+
+    // Like with bytes, an empty last line without a newline after it isn't a line
+    fn count_lines(&mut self) {
+        if let Contents::Lines(lines, newline_at_end) = &self.contents {
+            let unfinished = !newline_at_end && lines.last().is_some_and(String::is_empty);
+            self.line_count = lines.len() - unfinished as usize;
+        }
+    }
+
+    fn index(contents: Contents) -> Self {
+        let bytes = match &contents {
+            Contents::Empty | Contents::Lines(..) => &[][..],
+            Contents::Mapped(map) => &map[..],
+            Contents::Read(bytes) => &bytes[..],
+        };
+        let mut checkpoints = vec![0];
+        let mut line_count = 0;
+
+        for pos in memchr::memchr_iter(b'\n', bytes) {
+            line_count += 1;
+            if line_count % CHECKPOINT_STRIDE == 0 {
+                checkpoints.push(pos + 1);
+            }
+        }
+
+        // The last line doesn't need a newline to count
+        if bytes.last().is_some_and(|&b| b != b'\n') {
+            line_count += 1;
+        }
+
+        Self {
+            contents,
+            checkpoints,
+            line_count,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.line_count
+    }
+
+    /// The whole file. Files made from lines get joined back up
+    pub fn bytes(&self) -> Cow<'_, [u8]> {
+        match &self.contents {
+            Contents::Lines(..) => Cow::Owned(self.text(0..self.line_count).into_owned().into_bytes()),
+            _ => Cow::Borrowed(self.raw()),
+        }
+    }
+
+    fn raw(&self) -> &[u8] {
+        match &self.contents {
+            Contents::Empty | Contents::Lines(..) => &[],
+            Contents::Mapped(map) => map,
+            Contents::Read(bytes) => bytes,
+        }
+    }
+
+    /// Byte offset of the start of line `i`, or the end of the file if `i` is past the last line
+    fn line_start(&self, i: usize) -> usize {
+        if i >= self.line_count {
+            return self.raw().len();
+        }
+
+        let start = self.checkpoints[i / CHECKPOINT_STRIDE];
+        let skip = i % CHECKPOINT_STRIDE;
+        if skip == 0 {
+            return start;
+        }
+
+        memchr::memchr_iter(b'\n', &self.raw()[start..])
+            .nth(skip - 1)
+            .map(|pos| start + pos + 1)
+            .unwrap_or(self.raw().len())
+    }
+
+    /// Iterate over the lines starting at line `i`, without their terminators
+    pub fn lines_from(&self, i: usize) -> Box<dyn Iterator<Item = &[u8]> + '_> {
+        match &self.contents {
+            Contents::Lines(lines, _) => Box::new(lines[i.min(self.line_count)..self.line_count].iter().map(String::as_bytes)),
+            _ => Box::new(
+                self.raw()[self.line_start(i)..]
+                    .split(|&b| b == b'\n')
+                    .take(self.line_count.saturating_sub(i))
+            ),
+        }
+    }
+
+    /// Iterate over the lines backwards from the end of the file
+    pub fn lines_rev(&self) -> Box<dyn Iterator<Item = &[u8]> + '_> {
+        if let Contents::Lines(lines, _) = &self.contents {
+            return Box::new(lines[..self.line_count].iter().rev().map(String::as_bytes));
+        }
+
+        let bytes = self.raw();
+        let bytes = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+
+        Box::new(bytes.rsplit(|&b| b == b'\n').take(self.line_count))
+    }
+
+    /// The text of a range of lines, terminators included
+    pub fn text(&self, range: Range<usize>) -> Cow<'_, str> {
+        let Contents::Lines(lines, newline_at_end) = &self.contents else {
+            return String::from_utf8_lossy(&self.raw()[self.line_start(range.start)..self.line_start(range.end)]);
+        };
+
+        let range = range.start.min(self.line_count)..range.end.min(self.line_count);
+        let mut text = String::new();
+        for (line, n) in lines[range.clone()].iter().zip(range) {
+            text += line;
+            // The last line only has a newline if the file ends in one
+            if n + 1 < lines.len() || *newline_at_end {
+                text.push('\n');
+            }
+        }
+        Cow::Owned(text)
+    }
+}
@@ -0,0 +1,472 @@
+use std::{collections::BTreeMap, error::Error, fmt, str::FromStr};
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Deserialize, Serialize};
+
+/// Everything a key can be bound to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    ScrollUp,
+    ScrollDown,
+    ScrollLeft,
+    ScrollRight,
+    PageUp,
+    PageDown,
+    HalfPageUp,
+    HalfPageDown,
+    Top,
+    Bottom,
+    NextHunk,
+    PrevHunk,
+    SwitchSide,
+    ShrinkLeft,
+    GrowLeft,
+    Maximize,
+    ToggleLock,
+    Sync,
+    Visual,
+    CopyLine,
+    CopySelection,
+    CopyHunk,
+    CopyLeft,
+    CopyRight,
+    Edit,
+    Save,
+    OpenEditor,
+    CycleAlgorithm,
+    JumpMove,
+    Reset,
+    PickTheme,
+    ToggleHelp,
+    Quit,
+}
+
+impl Action {
+    // In the order they're listed on the help screen
+    pub const ALL: [Action; 33] = [
+        Action::ScrollUp,
+        Action::ScrollDown,
+        Action::ScrollLeft,
+        Action::ScrollRight,
+        Action::PageDown,
+        Action::PageUp,
+        Action::HalfPageDown,
+        Action::HalfPageUp,
+        Action::Top,
+        Action::Bottom,
+        Action::NextHunk,
+        Action::PrevHunk,
+        Action::SwitchSide,
+        Action::ShrinkLeft,
+        Action::GrowLeft,
+        Action::Maximize,
+        Action::ToggleLock,
+        Action::Sync,
+        Action::Visual,
+        Action::CopyLine,
+        Action::CopySelection,
+        Action::CopyHunk,
+        Action::CopyLeft,
+        Action::CopyRight,
+        Action::Edit,
+        Action::Save,
+        Action::OpenEditor,
+        Action::CycleAlgorithm,
+        Action::JumpMove,
+        Action::Reset,
+        Action::PickTheme,
+        Action::ToggleHelp,
+        Action::Quit,
+    ];
+
+    /// What the action is called in the config file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::ScrollUp => "scroll-up",
+            Action::ScrollDown => "scroll-down",
+            Action::ScrollLeft => "scroll-left",
+            Action::ScrollRight => "scroll-right",
+            Action::PageUp => "page-up",
+            Action::PageDown => "page-down",
+            Action::HalfPageUp => "half-page-up",
+            Action::HalfPageDown => "half-page-down",
+            Action::Top => "top",
+            Action::Bottom => "bottom",
+            Action::NextHunk => "next-hunk",
+            Action::PrevHunk => "prev-hunk",
+            Action::SwitchSide => "switch-side",
+            Action::ShrinkLeft => "shrink-left",
+            Action::GrowLeft => "grow-left",
+            Action::Maximize => "maximize",
+            Action::ToggleLock => "toggle-lock",
+            Action::Sync => "sync",
+            Action::Visual => "visual",
+            Action::CopyLine => "copy-line",
+            Action::CopySelection => "copy-selection",
+            Action::CopyHunk => "copy-hunk",
+            Action::CopyLeft => "copy-left",
+            Action::CopyRight => "copy-right",
+            Action::Edit => "edit",
+            Action::Save => "save",
+            Action::OpenEditor => "open-editor",
+            Action::CycleAlgorithm => "cycle-algorithm",
+            Action::JumpMove => "jump-move",
+            Action::Reset => "reset",
+            Action::PickTheme => "pick-theme",
+            Action::ToggleHelp => "toggle-help",
+            Action::Quit => "quit",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::ScrollUp => "scroll up",
+            Action::ScrollDown => "scroll down",
+            Action::ScrollLeft => "scroll left",
+            Action::ScrollRight => "scroll right",
+            Action::PageUp => "last page",
+            Action::PageDown => "next page",
+            Action::HalfPageUp => "half a page up",
+            Action::HalfPageDown => "half a page down",
+            Action::Top => "beginning of file",
+            Action::Bottom => "end of file",
+            Action::NextHunk => "next change",
+            Action::PrevHunk => "previous change",
+            Action::SwitchSide => "focus the other side",
+            Action::ShrinkLeft => "move the divider left (or up)",
+            Action::GrowLeft => "move the divider right (or down)",
+            Action::Maximize => "maximize the focused side",
+            Action::ToggleLock => "scroll the sides separately or together",
+            Action::Sync => "line the other side up with the focused one",
+            Action::Visual => "select lines with the movement keys",
+            Action::CopyLine => "copy the current line",
+            Action::CopySelection => "copy the selection",
+            Action::CopyHunk => "copy the change as a patch",
+            Action::CopyLeft => "copy the left side of the selection or change",
+            Action::CopyRight => "copy the right side of the selection or change",
+            Action::Edit => "edit the focused side ([esc] stops)",
+            Action::Save => "save the edited file",
+            Action::OpenEditor => "open the focused side in $EDITOR",
+            Action::CycleAlgorithm => "diff with the next algorithm",
+            Action::JumpMove => "jump to the other end of a moved block",
+            Action::Reset => "reset",
+            Action::PickTheme => "pick a theme",
+            Action::ToggleHelp => "help",
+            Action::Quit => "quit",
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Action::ALL.iter()
+            .find(|a| a.name() == s)
+            .copied()
+            .ok_or_else(|| format!("Unknown action {}", s))
+    }
+}
+
+/// A key plus the modifiers held with it, like `ctrl-d`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct KeyBinding {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        // Shift is already part of the character, and terminals don't agree on whether to report it
+        let modifiers = match code {
+            KeyCode::Char(_) => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers
+        };
+
+        Self { code, modifiers }
+    }
+}
+
+impl From<&KeyEvent> for KeyBinding {
+    fn from(key: &KeyEvent) -> Self {
+        Self::new(key.code, key.modifiers)
+    }
+}
+
+impl FromStr for KeyBinding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+
+        // Peel off the modifiers, leaving something like "-" alone
+        while let Some((modifier, key)) = rest.split_once('-').filter(|(_, key)| !key.is_empty()) {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => break
+            };
+            rest = key;
+        }
+
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::Char(c.to_ascii_uppercase()),
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match rest.to_lowercase().as_str() {
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "enter" => KeyCode::Enter,
+                "esc" => KeyCode::Esc,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "space" => KeyCode::Char(' '),
+                f => f.strip_prefix('f')
+                    .and_then(|n| n.parse::<u8>().ok())
+                    .map(KeyCode::F)
+                    .ok_or_else(|| format!("Unknown key {}", s))?
+            }
+        };
+
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "ctrl-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "alt-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "shift-")?;
+        }
+
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::Up => write!(f, "\u{2191}"),
+            KeyCode::Down => write!(f, "\u{2193}"),
+            KeyCode::Left => write!(f, "\u{2190}"),
+            KeyCode::Right => write!(f, "\u{2192}"),
+            KeyCode::PageUp => write!(f, "pageup"),
+            KeyCode::PageDown => write!(f, "pagedown"),
+            KeyCode::F(n) => write!(f, "f{}", n),
+            code => write!(f, "{}", code.to_string().to_lowercase().replace(' ', ""))
+        }
+    }
+}
+
+/// The built in sets of bindings to start from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    #[default]
+    Default,
+    Vim,
+    Emacs,
+}
+
+impl Preset {
+    fn bindings(&self) -> Vec<(&'static str, Action)> {
+        match self {
+            Preset::Default => vec![
+                ("up", Action::ScrollUp),
+                ("down", Action::ScrollDown),
+                ("left", Action::ScrollLeft),
+                ("right", Action::ScrollRight),
+                ("n", Action::PageDown),
+                ("pagedown", Action::PageDown),
+                ("l", Action::PageUp),
+                ("pageup", Action::PageUp),
+                ("ctrl-d", Action::HalfPageDown),
+                ("ctrl-u", Action::HalfPageUp),
+                ("b", Action::Top),
+                ("home", Action::Top),
+                ("e", Action::Bottom),
+                ("end", Action::Bottom),
+                ("]", Action::NextHunk),
+                ("[", Action::PrevHunk),
+                ("tab", Action::SwitchSide),
+                ("<", Action::ShrinkLeft),
+                (">", Action::GrowLeft),
+                ("z", Action::Maximize),
+                ("s", Action::ToggleLock),
+                ("=", Action::Sync),
+                ("v", Action::Visual),
+                ("Y", Action::CopyLine),
+                ("y", Action::CopySelection),
+                ("p", Action::CopyHunk),
+                ("H", Action::CopyLeft),
+                ("L", Action::CopyRight),
+                ("i", Action::Edit),
+                ("ctrl-s", Action::Save),
+                ("o", Action::OpenEditor),
+                ("a", Action::CycleAlgorithm),
+                ("m", Action::JumpMove),
+                ("r", Action::Reset),
+                ("t", Action::PickTheme),
+                ("h", Action::ToggleHelp),
+                ("q", Action::Quit),
+            ],
+            Preset::Vim => vec![
+                ("k", Action::ScrollUp),
+                ("up", Action::ScrollUp),
+                ("j", Action::ScrollDown),
+                ("down", Action::ScrollDown),
+                ("h", Action::ScrollLeft),
+                ("left", Action::ScrollLeft),
+                ("l", Action::ScrollRight),
+                ("right", Action::ScrollRight),
+                ("ctrl-f", Action::PageDown),
+                ("pagedown", Action::PageDown),
+                ("ctrl-b", Action::PageUp),
+                ("pageup", Action::PageUp),
+                ("ctrl-d", Action::HalfPageDown),
+                ("ctrl-u", Action::HalfPageUp),
+                ("g", Action::Top),
+                ("home", Action::Top),
+                ("G", Action::Bottom),
+                ("end", Action::Bottom),
+                ("n", Action::NextHunk),
+                ("]", Action::NextHunk),
+                ("N", Action::PrevHunk),
+                ("[", Action::PrevHunk),
+                ("tab", Action::SwitchSide),
+                ("<", Action::ShrinkLeft),
+                (">", Action::GrowLeft),
+                ("z", Action::Maximize),
+                ("s", Action::ToggleLock),
+                ("=", Action::Sync),
+                ("v", Action::Visual),
+                ("Y", Action::CopyLine),
+                ("y", Action::CopySelection),
+                ("p", Action::CopyHunk),
+                ("H", Action::CopyLeft),
+                ("L", Action::CopyRight),
+                ("i", Action::Edit),
+                ("ctrl-s", Action::Save),
+                ("o", Action::OpenEditor),
+                ("a", Action::CycleAlgorithm),
+                ("m", Action::JumpMove),
+                ("r", Action::Reset),
+                ("t", Action::PickTheme),
+                ("?", Action::ToggleHelp),
+                ("q", Action::Quit),
+            ],
+            Preset::Emacs => vec![
+                ("ctrl-p", Action::ScrollUp),
+                ("up", Action::ScrollUp),
+                ("ctrl-n", Action::ScrollDown),
+                ("down", Action::ScrollDown),
+                ("ctrl-b", Action::ScrollLeft),
+                ("left", Action::ScrollLeft),
+                ("ctrl-f", Action::ScrollRight),
+                ("right", Action::ScrollRight),
+                ("ctrl-v", Action::PageDown),
+                ("pagedown", Action::PageDown),
+                ("alt-v", Action::PageUp),
+                ("pageup", Action::PageUp),
+                ("alt-<", Action::Top),
+                ("home", Action::Top),
+                ("alt->", Action::Bottom),
+                ("end", Action::Bottom),
+                ("alt-}", Action::NextHunk),
+                ("alt-{", Action::PrevHunk),
+                ("alt-o", Action::SwitchSide),
+                ("alt-left", Action::ShrinkLeft),
+                ("alt-right", Action::GrowLeft),
+                ("alt-1", Action::Maximize),
+                ("alt-s", Action::ToggleLock),
+                ("alt-=", Action::Sync),
+                ("ctrl-space", Action::Visual),
+                ("alt-k", Action::CopyLine),
+                ("alt-w", Action::CopySelection),
+                ("alt-p", Action::CopyHunk),
+                ("alt-h", Action::CopyLeft),
+                ("alt-l", Action::CopyRight),
+                ("alt-e", Action::Edit),
+                ("ctrl-s", Action::Save),
+                ("ctrl-o", Action::OpenEditor),
+                ("alt-a", Action::CycleAlgorithm),
+                ("alt-m", Action::JumpMove),
+                ("ctrl-l", Action::Reset),
+                ("alt-t", Action::PickTheme),
+                ("ctrl-h", Action::ToggleHelp),
+                ("f1", Action::ToggleHelp),
+                ("q", Action::Quit),
+                ("ctrl-g", Action::Quit),
+            ],
+        }
+    }
+}
+
+/// Which action each key does. Kept in order so the help screen lists keys the way they were bound
+pub struct Keymap {
+    bindings: Vec<(KeyBinding, Action)>,
+}
+
+impl Keymap {
+    /// Start from `preset` and apply the user's bindings on top. Binding a key to "none" unbinds it
+    pub fn new(preset: Preset, overrides: &BTreeMap<String, String>) -> Result<Self, Box<dyn Error>> {
+        let mut keymap = Self { bindings: Vec::new() };
+
+        for (key, action) in preset.bindings() {
+            keymap.bind(key.parse::<KeyBinding>()?, Some(action));
+        }
+
+        for (key, action) in overrides {
+            let action = match action.as_str() {
+                "none" => None,
+                a => Some(a.parse::<Action>()?)
+            };
+            keymap.bind(key.parse::<KeyBinding>()?, action);
+        }
+
+        Ok(keymap)
+    }
+
+    fn bind(&mut self, key: KeyBinding, action: Option<Action>) {
+        self.bindings.retain(|&(k, _)| k != key);
+        if let Some(action) = action {
+            self.bindings.push((key, action));
+        }
+    }
+
+    pub fn action(&self, key: &KeyEvent) -> Option<Action> {
+        let key = KeyBinding::from(key);
+        self.bindings.iter().find(|&&(k, _)| k == key).map(|&(_, a)| a)
+    }
+
+    /// Like `[n] next page`, using the first key bound to the action
+    pub fn hint(&self, action: Action) -> Option<String> {
+        self.bindings.iter()
+            .find(|&&(_, a)| a == action)
+            .map(|(k, a)| format!("[{}] {}", k, a.description()))
+    }
+
+    /// One line per bound action with all of its keys
+    pub fn help(&self) -> Vec<String> {
+        Action::ALL.iter().filter_map(|&action| {
+            let keys = self.bindings.iter()
+                .filter(|&&(_, a)| a == action)
+                .map(|(k, _)| k.to_string())
+                .collect::<Vec<String>>();
+
+            (!keys.is_empty()).then(|| format!("[{}] {}", keys.join(", "), action.description()))
+        })
+        .collect::<Vec<String>>()
+    }
+}
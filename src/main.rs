@@ -1,18 +1,43 @@
+mod algorithm;
 mod app;
 mod args;
+mod clipboard;
+mod compare;
+mod config;
+mod diff;
+mod edit;
 mod helpers;
+mod highlight;
+mod input;
+mod keymap;
+mod moves;
+mod structured;
 mod ui;
+mod watch;
 
 use ratatui::crossterm::{event::{DisableMouseCapture, EnableMouseCapture}, execute, terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen}};
 use ratatui::backend::CrosstermBackend;
 use ratatui::Terminal;
 use std::io::stdout;
+use std::process;
 use std::error::Error;
 
 use app::App;
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let mut app = App::new();
+    let mut app = match App::new() {
+        Ok(app) => app,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
+
+    // Only dump the settings, no need for the terminal
+    if let Some(config) = app.printable_config()? {
+        print!("{}", config);
+        return Ok(());
+    }
 
     let mut stdout = stdout();
     enable_raw_mode()?;
@@ -34,7 +59,8 @@ fn main() -> Result<(), Box<dyn Error>> {
     terminal.show_cursor()?;
 
     if let Err(err) = res {
-        eprintln!("{}", err.to_string());
+        eprintln!("{}", err);
+        process::exit(1);
     }
 
     Ok(())
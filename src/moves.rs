@@ -0,0 +1,162 @@
+use std::{collections::HashMap, ops::Range};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::compare::Comparator;
+use crate::diff::Diff;
+use crate::input::MappedFile;
+
+// Like git, a block has to have this many letters and digits in it to count as moved, so
+// runs of braces and blank lines don't light up everywhere
+const MIN_ALNUM: usize = 20;
+
+// Lines that turn up more often than this among the insertions can't start a move
+const MAX_CANDIDATES: usize = 16;
+
+// Past this many changed lines, looking for moves would hold up the UI and keep all of them in memory
+// for something nobody reads through at that size anyway
+const MAX_CHANGED: usize = 100_000;
+
+/// How to look for blocks of lines that moved
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum MoveDetection {
+    /// Don't look for moves
+    Off,
+    /// Lines have to match the way the diff compares them
+    #[default]
+    Exact,
+    /// Also ignore indentation, for code that moved in or out of a block
+    IgnoreIndent,
+}
+
+/// Lines deleted in one place and inserted somewhere else
+#[derive(Clone, Debug)]
+pub struct Move {
+    pub left: Range<usize>,
+    pub right: Range<usize>,
+}
+
+impl Move {
+    /// The lines on side `i`
+    pub fn side(&self, i: usize) -> &Range<usize> {
+        match i {
+            0 => &self.left,
+            _ => &self.right,
+        }
+    }
+}
+
+/// Every move in a diff, found by matching up the deleted lines with the inserted ones
+#[derive(Default)]
+pub struct Moves {
+    moves: Vec<Move>,
+    // Indexes into `moves`, by where they are on the right
+    by_right: Vec<usize>,
+}
+
+impl Moves {
+    pub fn find(diff: &Diff, left: &MappedFile, right: &MappedFile, cmp: &Comparator, detection: MoveDetection) -> Self {
+        if detection == MoveDetection::Off {
+            return Self::default();
+        }
+
+        let (deleted, inserted) = (merge(diff.changed_lines(0)), merge(diff.changed_lines(1)));
+        if deleted.iter().chain(&inserted).map(|range| range.len()).sum::<usize>() > MAX_CHANGED {
+            return Self::default();
+        }
+
+        let key = |line: &[u8]| -> Vec<u8> {
+            let key = cmp.key(line);
+            match detection {
+                MoveDetection::IgnoreIndent => key.trim_ascii_start().to_vec(),
+                _ => key.into_owned()
+            }
+        };
+
+        // Every inserted line, by what it looks like. Lines come one block after another so a run of
+        // them can be followed without looking anything up
+        let lines_right = inserted.iter()
+            .flat_map(|range| right.lines_from(range.start).take(range.len()).zip(range.clone()))
+            .map(|(line, j)| (j, key(line)))
+            .collect::<Vec<(usize, Vec<u8>)>>();
+        let mut candidates: HashMap<&[u8], Vec<usize>> = HashMap::new();
+        for (k, (_, line)) in lines_right.iter().enumerate() {
+            candidates.entry(line).or_default().push(k);
+        }
+        // Lines that show up all over, like blank lines and lone braces, can be part of a move but
+        // never start one, otherwise every one of them gets tried against every other
+        let seeds = |line: &[u8]| candidates.get(line).filter(|c| c.len() <= MAX_CANDIDATES);
+        let follows = |k: usize| k > 0 && lines_right[k - 1].0 + 1 == lines_right[k].0;
+
+        let mut moves = Vec::new();
+        let mut taken = vec![false; lines_right.len()];
+        for range in deleted {
+            let lines = left.lines_from(range.start).take(range.len()).map(key).collect::<Vec<Vec<u8>>>();
+
+            let mut i = 0;
+            while i < lines.len() {
+                // The longest run of inserted lines matching from here
+                let best = seeds(&lines[i]).into_iter().flatten()
+                    .filter(|&&k| !taken[k])
+                    // A run that could've started on the line before already got tried from there
+                    .filter(|&&k| !(i > 0 && seeds(&lines[i - 1]).is_some() && follows(k) && !taken[k - 1] && lines_right[k - 1].1 == lines[i - 1]))
+                    // Lines that only got reindented in place aren't moves
+                    .filter(|&&k| diff.row_of(0, range.start + i) != diff.row_of(1, lines_right[k].0))
+                    .map(|&k| {
+                        let len = 1 + (k + 1..lines_right.len())
+                            .zip(&lines[i + 1..])
+                            .take_while(|&(k, line)| follows(k) && lines_right[k].1 == *line && !taken[k])
+                            .count();
+                        (k, len)
+                    })
+                    .max_by_key(|&(k, len)| (len, std::cmp::Reverse(k)));
+
+                let Some((k, len)) = best.filter(|&(_, len)| alnum(&lines[i..i + len]) >= MIN_ALNUM) else {
+                    i += 1;
+                    continue;
+                };
+
+                taken[k..k + len].fill(true);
+                let j = lines_right[k].0;
+                moves.push(Move { left: range.start + i..range.start + i + len, right: j..j + len });
+                i += len;
+            }
+        }
+
+        let mut by_right = (0..moves.len()).collect::<Vec<usize>>();
+        by_right.sort_unstable_by_key(|&m| moves[m].right.start);
+
+        Self { moves, by_right }
+    }
+
+    pub fn len(&self) -> usize {
+        self.moves.len()
+    }
+
+    /// The move line `line` of side `i` is part of
+    pub fn at(&self, i: usize, line: usize) -> Option<&Move> {
+        let found = match i {
+            0 => self.moves.get(self.moves.partition_point(|m| m.left.end <= line)),
+            _ => self.by_right.get(self.by_right.partition_point(|&m| self.moves[m].right.end <= line)).map(|&m| &self.moves[m]),
+        };
+
+        found.filter(|m| m.side(i).contains(&line))
+    }
+}
+
+/// Join up ranges that touch, like a change followed by a deletion
+fn merge(ranges: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges.into_iter().filter(|r| !r.is_empty()) {
+        match merged.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => merged.push(range)
+        }
+    }
+    merged
+}
+
+fn alnum(lines: &[Vec<u8>]) -> usize {
+    lines.iter().map(|line| line.iter().filter(|b| b.is_ascii_alphanumeric()).count()).sum()
+}
@@ -0,0 +1,650 @@
+use std::{cmp::Ordering, path::Path};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::compare::Mask;
+
+/// Whether to parse the files and diff them by structure instead of line by line
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Structured {
+    /// When both files have the extension of a format that can be
+    #[default]
+    Auto,
+    /// Always diff line by line
+    Off,
+    /// JSON, ignoring key order and formatting
+    Json,
+    /// YAML, with the documents of a stream matched up by `kind` and `metadata.name`
+    Yaml,
+    /// TOML, ignoring key and table order and formatting
+    Toml,
+    /// Comma separated values, shown as a table with the columns matched up by name
+    Csv,
+    /// Tab separated values, like csv
+    Tsv,
+}
+
+impl Structured {
+    /// The format to parse `paths` as, if any. Auto goes by what both extensions agree on
+    pub fn resolve(self, paths: [&str; 2]) -> Option<Self> {
+        match self {
+            Self::Auto => {
+                let [left, right] = paths.map(Self::from_extension);
+                left.filter(|_| left == right)
+            },
+            Self::Off => None,
+            format => Some(format),
+        }
+    }
+
+    fn from_extension(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "json" => Some(Self::Json),
+            "yaml" | "yml" => Some(Self::Yaml),
+            "toml" => Some(Self::Toml),
+            "csv" => Some(Self::Csv),
+            "tsv" | "tab" => Some(Self::Tsv),
+            _ => None,
+        }
+    }
+
+    /// Masks so the way documents get printed doesn't count, like the comma that turns up on the line
+    /// before anything added to the end of an object
+    pub fn masks(self) -> Vec<Mask> {
+        match self {
+            Self::Json => vec![Mask { pattern: String::from(",$"), replace: String::new() }],
+            // Columns only one side has get written after a separator, so they don't make every row a change
+            Self::Csv | Self::Tsv => vec![Mask { pattern: format!("{}.*", EXTRA), replace: String::new() }],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Whether it's diffed as a table, which needs both sides at once to match up the columns
+    pub fn is_table(self) -> bool {
+        matches!(self, Self::Csv | Self::Tsv)
+    }
+
+    /// The name it goes by on the command line, which also works for finding its syntax
+    pub fn name(self) -> String {
+        self.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default()
+    }
+}
+
+/// A parsed value, whatever format it came from
+enum Node {
+    /// Written the way the format writes it
+    Scalar(String),
+    /// Sorted by key
+    Map(Vec<(String, Node)>),
+    List(Vec<Node>),
+}
+
+impl Node {
+    fn map(mut entries: Vec<(String, Node)>) -> Self {
+        entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        Self::Map(entries)
+    }
+
+    fn from_json(value: serde_json::Value) -> Self {
+        use serde_json::Value;
+        match value {
+            Value::Object(map) => Self::map(map.into_iter().map(|(k, v)| (k, Self::from_json(v))).collect()),
+            Value::Array(items) => Self::List(items.into_iter().map(Self::from_json).collect()),
+            value => Self::Scalar(value.to_string()),
+        }
+    }
+
+    fn from_yaml(value: serde_yaml::Value) -> Self {
+        use serde_yaml::Value;
+        match value {
+            Value::Mapping(map) => Self::map(map.into_iter().map(|(k, v)| (yaml_key(k), Self::from_yaml(v))).collect()),
+            Value::Sequence(items) => Self::List(items.into_iter().map(Self::from_yaml).collect()),
+            Value::Tagged(tagged) => match Self::from_yaml(tagged.value) {
+                Self::Scalar(value) => Self::Scalar(format!("{} {}", tagged.tag, value)),
+                // Tags on collections don't get shown, there's no line of their own to put them on
+                node => node,
+            },
+            value => Self::Scalar(yaml_scalar(&value)),
+        }
+    }
+
+    fn from_toml(value: toml::Value) -> Self {
+        use toml::Value;
+        match value {
+            Value::Table(table) => Self::map(table.into_iter().map(|(k, v)| (k, Self::from_toml(v))).collect()),
+            Value::Array(items) => Self::List(items.into_iter().map(Self::from_toml).collect()),
+            // JSON's escapes are all ones TOML understands too
+            Value::String(s) => Self::Scalar(serde_json::Value::from(s).to_string()),
+            Value::Datetime(datetime) => Self::Scalar(datetime.to_string()),
+            value => Self::Scalar(value.to_string()),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            Self::Scalar(_) => false,
+            Self::Map(entries) => entries.is_empty(),
+            Self::List(items) => items.is_empty(),
+        }
+    }
+
+    /// Whether TOML needs a `[table]` of its own for it rather than writing it on one line
+    fn is_table(&self) -> bool {
+        matches!(self, Self::Map(_))
+    }
+
+    fn is_table_array(&self) -> bool {
+        matches!(self, Self::List(items) if !items.is_empty() && items.iter().all(Self::is_table))
+    }
+}
+
+/// A file parsed and printed back out the same way every time, so only real differences show up
+pub struct Document {
+    pub text: Vec<u8>,
+    /// Where in the document each line is, like `$.items[3].price`
+    pub paths: Vec<String>,
+}
+
+impl Document {
+    pub fn parse(format: Structured, bytes: &[u8]) -> Result<Self, String> {
+        let mut document = Self { text: Vec::new(), paths: Vec::new() };
+        match format {
+            Structured::Json => {
+                let value = serde_json::from_slice(bytes).map_err(|e| format!("isn't valid JSON: {}", e))?;
+                document.json(Node::from_json(value), String::from("$"), None, 0, false);
+            },
+            Structured::Yaml => {
+                let mut documents = Vec::new();
+                for deserializer in serde_yaml::Deserializer::from_slice(bytes) {
+                    let value = serde_yaml::Value::deserialize(deserializer).map_err(|e| format!("isn't valid YAML: {}", e))?;
+                    documents.push((k8s_name(&value), documents.len(), Node::from_yaml(value)));
+                }
+
+                // Put the documents in the same order on both sides, so the ones with the same kind and
+                // name line up wherever they were in the stream. The rest keep where they were, and get
+                // called by that
+                documents.sort_by(|a, b| a.0.cmp(&b.0));
+                let stream = documents.len() > 1;
+                for (name, i, node) in documents {
+                    let root = match (stream, name) {
+                        (false, _) => String::from("$"),
+                        (true, Some(name)) => format!("{} $", name),
+                        (true, None) => format!("#{} $", i + 1),
+                    };
+                    if stream {
+                        document.push(0, "---", &root);
+                    }
+                    document.yaml(node, &root, 0);
+                }
+            },
+            Structured::Toml => {
+                let text = std::str::from_utf8(bytes).map_err(|_| String::from("isn't valid TOML: not UTF-8"))?;
+                let table = text.parse::<toml::Table>().map_err(|e| {
+                    let line = e.span().map_or(1, |span| memchr::memchr_iter(b'\n', &bytes[..span.start]).count() + 1);
+                    format!("isn't valid TOML: {} at line {}", e.message().replace('\n', ", "), line)
+                })?;
+                let Node::Map(entries) = Node::from_toml(toml::Value::Table(table)) else {
+                    unreachable!("a table is a map");
+                };
+                document.toml(entries, "$", "");
+            },
+            Structured::Csv | Structured::Tsv => unreachable!("tables get parsed with Table::parse"),
+            Structured::Auto | Structured::Off => unreachable!("only formats get parsed"),
+        }
+        Ok(document)
+    }
+
+    fn push(&mut self, depth: usize, line: &str, path: &str) {
+        self.text.extend(std::iter::repeat_n(b' ', depth * 2));
+        self.text.extend(line.as_bytes());
+        self.text.push(b'\n');
+        self.paths.push(path.to_string());
+    }
+
+    /// Pretty print `node` as JSON with two space indents
+    fn json(&mut self, node: Node, path: String, key: Option<String>, depth: usize, comma: bool) {
+        let key = key.map(|k| format!("{}: ", serde_json::Value::from(k))).unwrap_or_default();
+        let comma = if comma { "," } else { "" };
+
+        let (open, close, children) = match node {
+            Node::Map(entries) if !entries.is_empty() => {
+                let children = entries.into_iter()
+                    .map(|(k, v)| (object_path(&path, &k), Some(k), v))
+                    .collect::<Vec<(String, Option<String>, Node)>>();
+                ("{", "}", children)
+            },
+            Node::List(items) if !items.is_empty() => {
+                let children = items.into_iter().enumerate()
+                    .map(|(i, v)| (format!("{}[{}]", path, i), None, v))
+                    .collect::<Vec<(String, Option<String>, Node)>>();
+                ("[", "]", children)
+            },
+            Node::Map(_) => return self.push(depth, &format!("{}{{}}{}", key, comma), &path),
+            Node::List(_) => return self.push(depth, &format!("{}[]{}", key, comma), &path),
+            Node::Scalar(value) => return self.push(depth, &format!("{}{}{}", key, value, comma), &path),
+        };
+
+        self.push(depth, &format!("{}{}", key, open), &path);
+        let last = children.len() - 1;
+        for (i, (child_path, child_key, child)) in children.into_iter().enumerate() {
+            self.json(child, child_path, child_key, depth + 1, i < last);
+        }
+        self.push(depth, &format!("{}{}", close, comma), &path);
+    }
+
+    /// Print the insides of `node` as YAML, starting `depth` levels in
+    fn yaml(&mut self, node: Node, path: &str, depth: usize) {
+        match node {
+            Node::Map(entries) if !entries.is_empty() => for (k, v) in entries {
+                self.yaml_item(v, &object_path(path, &k), &format!("{}:", yaml_key_text(&k)), depth);
+            },
+            Node::List(items) if !items.is_empty() => for (i, v) in items.into_iter().enumerate() {
+                self.yaml_item(v, &format!("{}[{}]", path, i), "-", depth);
+            },
+            node => self.push(depth, &inline_yaml(&node), path),
+        }
+    }
+
+    /// One `key:` or `-` and what goes with it. Maps in lists start on the dash line, the way they usually get written
+    fn yaml_item(&mut self, node: Node, path: &str, lead: &str, depth: usize) {
+        match node {
+            Node::Map(entries) if lead == "-" && !entries.is_empty() => {
+                let mut entries = entries.into_iter();
+                if let Some((k, v)) = entries.next() {
+                    self.yaml_item(v, &object_path(path, &k), &format!("- {}:", yaml_key_text(&k)), depth);
+                }
+                for (k, v) in entries {
+                    self.yaml_item(v, &object_path(path, &k), &format!("{}:", yaml_key_text(&k)), depth + 1);
+                }
+            },
+            // The first key of a map in a list sits after the dash, so what's under it goes one level further in
+            node if !node.is_empty() && !matches!(node, Node::Scalar(_)) => {
+                self.push(depth, lead, path);
+                self.yaml(node, path, depth + 1 + usize::from(lead.starts_with("- ")));
+            },
+            node => self.push(depth, &format!("{} {}", lead, inline_yaml(&node)), path),
+        }
+    }
+
+    /// Print a table as TOML: its keys first, then its tables, then its arrays of tables, each under a header
+    fn toml(&mut self, entries: Vec<(String, Node)>, path: &str, prefix: &str) {
+        let (inline, tables): (Vec<_>, Vec<_>) = entries.into_iter()
+            .partition(|(_, v)| !v.is_table() && !v.is_table_array());
+
+        for (k, v) in inline {
+            self.push(0, &format!("{} = {}", toml_key(&k), inline_toml(&v)), &object_path(path, &k));
+        }
+
+        let (tables, arrays): (Vec<_>, Vec<_>) = tables.into_iter().partition(|(_, v)| v.is_table());
+        for (k, v) in tables.into_iter().chain(arrays) {
+            let name = format!("{}{}", prefix, toml_key(&k));
+            let path = object_path(path, &k);
+            match v {
+                Node::Map(entries) => {
+                    self.push(0, &format!("[{}]", name), &path);
+                    self.toml(entries, &path, &format!("{}.", name));
+                },
+                Node::List(items) => for (i, item) in items.into_iter().enumerate() {
+                    let path = format!("{}[{}]", path, i);
+                    self.push(0, &format!("[[{}]]", name), &path);
+                    if let Node::Map(entries) = item {
+                        self.toml(entries, &path, &format!("{}.", name));
+                    }
+                },
+                Node::Scalar(_) => unreachable!("scalars are written inline"),
+            }
+        }
+    }
+}
+
+// How the cells of a table row get written out as a line: the cells of the columns both sides have, then the
+// ones only this side has after EXTRA. Tabs, newlines and backslashes in cells get escaped, and stay that way
+// when they're shown
+const CELL: char = '\t';
+const EXTRA: char = '\x1e';
+
+/// The header and rows of a CSV or TSV file
+pub struct Table {
+    header: Vec<String>,
+    // The cells of each row, and the line it starts on
+    rows: Vec<(Vec<String>, u64)>,
+}
+
+impl Table {
+    pub fn parse(format: Structured, bytes: &[u8]) -> Result<Self, String> {
+        let delimiter = if format == Structured::Tsv { b'\t' } else { b',' };
+        let mut reader = csv::ReaderBuilder::new().delimiter(delimiter).flexible(true).from_reader(bytes);
+        let invalid = |e: csv::Error| format!("isn't valid {}: {}", format.name().to_uppercase(), e);
+
+        let header = reader.headers().map_err(invalid)?.iter().map(String::from).collect();
+        let rows = reader.records()
+            .map(|record| {
+                let record = record.map_err(invalid)?;
+                let line = record.position().map_or(0, |p| p.line());
+                Ok((record.iter().map(String::from).collect(), line))
+            })
+            .collect::<Result<Vec<(Vec<String>, u64)>, String>>()?;
+
+        Ok(Self { header, rows })
+    }
+
+    fn column(&self, key: &str) -> Option<usize> {
+        self.header.iter().position(|name| name == key)
+            .or_else(|| key.parse::<usize>().ok().filter(|&n| n >= 1 && n <= self.header.len()).map(|n| n - 1))
+    }
+}
+
+/// How the columns of both sides of a table diff line up. Every column either side has gets a place,
+/// so the sides show the same columns in the same spots
+pub struct Columns {
+    /// With a key column, the rows of each side that go together, in the order they're shown. A row
+    /// with a key the other side doesn't have goes by itself
+    pub matched: Option<Vec<[Option<usize>; 2]>>,
+    pub names: Vec<String>,
+    /// Which sides have each column
+    pub sides: Vec<[bool; 2]>,
+    /// How wide each column gets shown, in characters
+    pub widths: Vec<usize>,
+    // For each side, the column of each cell in the order they get written on a line
+    order: [Vec<usize>; 2],
+    // How many of those are columns both sides have
+    common: usize,
+}
+
+impl Columns {
+    /// The cells of a line of side `i` by column, still escaped so they stay on one line. Columns the
+    /// side doesn't have come out as None
+    pub fn cells(&self, i: usize, line: &[u8]) -> Vec<Option<String>> {
+        let line = String::from_utf8_lossy(line);
+        let (common, extra) = match line.split_once(EXTRA) {
+            Some((common, extra)) => (common, Some(extra)),
+            None => (&*line, None),
+        };
+
+        let mut cells = vec![None; self.names.len()];
+        let written = common.split(CELL).take(self.common).chain(extra.into_iter().flat_map(|e| e.split(CELL)));
+        for (&column, cell) in self.order[i].iter().zip(written) {
+            cells[column] = Some(cell.to_string());
+        }
+        cells
+    }
+}
+
+impl Document {
+    /// Write out the rows of both tables with their columns matched up by name. Rows line up by the
+    /// `key` column, by name or counting from 1, or by where they are if there isn't one
+    pub fn tables(tables: [Table; 2], key: Option<&str>) -> Result<([Self; 2], Columns), String> {
+        // The left side's columns in order, then the ones only the right side has
+        let mut names = tables[0].header.clone();
+        names.extend(tables[1].header.iter().filter(|name| !tables[0].header.contains(name)).cloned());
+        let sides = names.iter().map(|name| [0, 1].map(|i| tables[i].header.contains(name))).collect::<Vec<[bool; 2]>>();
+
+        let keys = match key {
+            Some(key) => {
+                let [left, right] = [0, 1].map(|i| tables[i].column(key));
+                match (left, right) {
+                    (Some(left), Some(right)) => Some([left, right]),
+                    _ => return Err(format!("both files need a {} column to line the rows up by", key)),
+                }
+            },
+            None => None,
+        };
+
+        // Sort both sides by their keys and walk them together, pairing up rows with the same key
+        let sorted = [0, 1].map(|i| {
+            let mut rows = tables[i].rows.iter().collect::<Vec<&(Vec<String>, u64)>>();
+            if let Some(keys) = keys {
+                rows.sort_by(|a, b| compare_keys(cell(&a.0, keys[i]), cell(&b.0, keys[i])));
+            }
+            rows
+        });
+        let matched = keys.map(|keys| {
+            let key = |i: usize, row: usize| cell(&sorted[i][row].0, keys[i]);
+            let (mut l, mut r) = (0, 0);
+            let mut matched = Vec::with_capacity(std::cmp::max(sorted[0].len(), sorted[1].len()));
+            while l < sorted[0].len() || r < sorted[1].len() {
+                let order = match (l < sorted[0].len(), r < sorted[1].len()) {
+                    (true, true) => compare_keys(key(0, l), key(1, r)),
+                    (true, false) => Ordering::Less,
+                    _ => Ordering::Greater,
+                };
+                match order {
+                    Ordering::Less => {
+                        matched.push([Some(l), None]);
+                        l += 1;
+                    },
+                    Ordering::Greater => {
+                        matched.push([None, Some(r)]);
+                        r += 1;
+                    },
+                    Ordering::Equal => {
+                        matched.push([Some(l), Some(r)]);
+                        l += 1;
+                        r += 1;
+                    },
+                }
+            }
+            matched
+        });
+
+        let mut widths = names.iter().map(|name| name.chars().count()).collect::<Vec<usize>>();
+        let mut order: [Vec<usize>; 2] = [Vec::new(), Vec::new()];
+        let common = sides.iter().filter(|&&s| s == [true, true]).count();
+        let documents = [0, 1].map(|i| {
+            let table = &tables[i];
+            // Where each of this side's cells goes, common columns first
+            let columns = table.header.iter()
+                .map(|name| names.iter().position(|n| n == name).unwrap_or_default())
+                .collect::<Vec<usize>>();
+            let mut written = (0..names.len())
+                .filter(|&c| sides[c] == [true, true])
+                .filter_map(|c| columns.iter().position(|&n| n == c))
+                .collect::<Vec<usize>>();
+            written.extend((0..columns.len()).filter(|&c| sides[columns[c]] != [true, true]));
+            order[i] = written.iter().map(|&c| columns[c]).collect();
+
+            let mut document = Self { text: Vec::new(), paths: Vec::new() };
+            for &(cells, line) in &sorted[i] {
+                for (&c, &column) in written.iter().zip(&order[i]) {
+                    widths[column] = widths[column].max(escape(cell(cells, c)).chars().count());
+                }
+
+                let escaped = written.iter().map(|&c| escape(cell(cells, c))).collect::<Vec<String>>();
+                let mut text = escaped[..common].join(&CELL.to_string());
+                if escaped.len() > common {
+                    text.push(EXTRA);
+                    text += &escaped[common..].join(&CELL.to_string());
+                }
+
+                let path = match keys {
+                    Some(keys) => format!("{}={} (line {})", table.header[keys[i]], cell(cells, keys[i]), line),
+                    None => format!("line {}", line),
+                };
+                document.push(0, &text, &path);
+            }
+            document
+        });
+
+        Ok((documents, Columns { matched, names, sides, widths, order, common }))
+    }
+}
+
+fn cell(cells: &[String], column: usize) -> &str {
+    cells.get(column).map_or("", String::as_str)
+}
+
+/// Numbers go in numeric order, everything else by its text
+fn compare_keys(a: &str, b: &str) -> Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.total_cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+fn escape(cell: &str) -> String {
+    cell.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+/// `.key` for keys that look like identifiers, `["key"]` for the rest
+fn object_path(parent: &str, key: &str) -> String {
+    let identifier = key.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    match identifier {
+        true => format!("{}.{}", parent, key),
+        false => format!("{}[{}]", parent, serde_json::Value::from(key)),
+    }
+}
+
+/// `Kind/name` for documents that look like Kubernetes objects, with the namespace if there is one
+fn k8s_name(value: &serde_yaml::Value) -> Option<String> {
+    let kind = value.get("kind")?.as_str()?;
+    let metadata = value.get("metadata")?;
+    let name = metadata.get("name")?.as_str()?;
+    Some(match metadata.get("namespace").and_then(|n| n.as_str()) {
+        Some(namespace) => format!("{}/{}/{}", kind, namespace, name),
+        None => format!("{}/{}", kind, name),
+    })
+}
+
+/// A scalar written the way serde_yaml would write it on its own, quotes and all. Strings that would
+/// take up more than one line get double quoted instead, which YAML reads the same as JSON does
+fn yaml_scalar(value: &serde_yaml::Value) -> String {
+    let text = serde_yaml::to_string(value).map(|s| s.trim_end().to_string()).unwrap_or_default();
+    match value.as_str() {
+        Some(s) if text.contains('\n') => serde_json::Value::from(s).to_string(),
+        _ => text,
+    }
+}
+
+fn yaml_key(key: serde_yaml::Value) -> String {
+    match key {
+        serde_yaml::Value::String(key) => key,
+        key => yaml_scalar(&key),
+    }
+}
+
+fn yaml_key_text(key: &str) -> String {
+    yaml_scalar(&serde_yaml::Value::String(key.to_string()))
+}
+
+fn inline_yaml(node: &Node) -> String {
+    match node {
+        Node::Scalar(value) => value.clone(),
+        Node::Map(entries) => format!("{{{}}}", entries.iter()
+            .map(|(k, v)| format!("{}: {}", yaml_key_text(k), inline_yaml(v)))
+            .collect::<Vec<String>>()
+            .join(", ")),
+        Node::List(items) => format!("[{}]", items.iter().map(inline_yaml).collect::<Vec<String>>().join(", ")),
+    }
+}
+
+/// Bare keys can only have letters, digits, `_` and `-` in them
+fn toml_key(key: &str) -> String {
+    match !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        true => key.to_string(),
+        false => serde_json::Value::from(key).to_string(),
+    }
+}
+
+fn inline_toml(node: &Node) -> String {
+    match node {
+        Node::Scalar(value) => value.clone(),
+        Node::Map(entries) if entries.is_empty() => String::from("{}"),
+        Node::Map(entries) => format!("{{ {} }}", entries.iter()
+            .map(|(k, v)| format!("{} = {}", toml_key(k), inline_toml(v)))
+            .collect::<Vec<String>>()
+            .join(", ")),
+        Node::List(items) => format!("[{}]", items.iter().map(inline_toml).collect::<Vec<String>>().join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn print(format: Structured, text: &str) -> String {
+        String::from_utf8(Document::parse(format, text.as_bytes()).unwrap().text).unwrap()
+    }
+
+    #[test]
+    fn json_numbers_keep_their_digits() {
+        let left = print(Structured::Json, r#"{"id": 12345678901234567890123, "p": 0.10000000000000000001}"#);
+        let right = print(Structured::Json, r#"{"id": 12345678901234567890124, "p": 0.1}"#);
+        assert!(left.contains("12345678901234567890123"));
+        assert!(left.contains("0.10000000000000000001"));
+        assert_ne!(left, right);
+    }
+
+    // Key order is the only thing that differs, so there's nothing left to diff once they're printed
+    #[test]
+    fn reordered_keys_print_the_same() {
+        let pairs = [
+            (Structured::Json, r#"{"b": [1, {"y": 2, "x": 1}], "a": {"d": null, "c": "s"}}"#, r#"{"a": {"c": "s", "d": null}, "b": [1, {"x": 1, "y": 2}]}"#),
+            (Structured::Yaml, "b:\n  - 1\n  - {y: 2, x: 1}\na: {d: ~, c: s}\n", "a:\n  c: s\n  d: null\nb: [1, {x: 1, y: 2}]\n"),
+            (Structured::Toml, "b = 1\na = [{ y = 2, x = 1 }]\n[t]\ny = 2\nx = 1\n", "a = [{ x = 1, y = 2 }]\nb = 1\n\n[t]\nx = 1\ny = 2\n"),
+        ];
+        for (format, left, right) in pairs {
+            assert_eq!(print(format, left), print(format, right), "{:?}", format);
+        }
+    }
+
+    // Printing is only for looking at, but it's still meant to be the same document. Keys all turn into
+    // strings, like they are in JSON, so these ones already are
+    #[test]
+    fn printed_toml_and_yaml_parse_back() {
+        let toml = "title = \"a \\\"quoted\\\" \\\\ string\"\n\"odd key\" = [1, 2.5, true, { x = [] }]\nwhen = 1979-05-27T07:32:00Z\n\
+            [[arr]]\nname = \"a\"\n[arr.sub]\nx = 1\n[[arr]]\nname = \"b\"\n[[arr.sub.deep]]\ny = [[1], [{ z = 3 }]]\n[t.\"dotted.key\"]\nv = \"\"\n";
+        let printed = print(Structured::Toml, toml);
+        assert_eq!(printed.parse::<toml::Table>().unwrap(), toml.parse::<toml::Table>().unwrap(), "printed as\n{}", printed);
+
+        let yaml = "b: [1, {c: 2, \"d: e\": \"#not a comment\"}]\na: \"x: y\"\nempty: {}\nnone: []\n\
+            list:\n  - - 1\n    - 2\n  - {}\n  - multi\n    line\n'quoted key': -.inf\n'true': 1\n";
+        let printed = print(Structured::Yaml, yaml);
+        let value = |text: &str| serde_yaml::from_str::<serde_yaml::Value>(text).unwrap();
+        assert_eq!(value(&printed), value(yaml), "printed as\n{}", printed);
+    }
+
+    // Tables in an array of tables go under the element they're in, however deep
+    #[test]
+    fn toml_table_arrays_keep_their_tables() {
+        let printed = print(Structured::Toml, "[[arr]]\nname = \"a\"\n[arr.sub]\nx = 1\n[[arr]]\nname = \"b\"\n[arr.sub]\nx = 2\n");
+        let headers = printed.lines().filter(|line| line.starts_with('[')).collect::<Vec<&str>>();
+        assert_eq!(headers, ["[[arr]]", "[arr.sub]", "[[arr]]", "[arr.sub]"]);
+        let table = printed.parse::<toml::Table>().unwrap();
+        assert_eq!(table["arr"][1]["sub"]["x"].as_integer(), Some(2));
+    }
+
+    // Rows pair up by key whatever order they're in, and each side's cells land in the columns they came from
+    #[test]
+    fn keyed_tables_with_some_columns_in_common() {
+        let table = |text: &str| Table::parse(Structured::Csv, text.as_bytes()).unwrap();
+        let left = table("id,name,age\n2,b,30\n10,c,5\n1,\"a\ta\",20\n");
+        let right = table("email,id,name\nb@x,2,bee\nz@x,3,z\na@x,1,a\ta\n");
+        let ([left, right], columns) = Document::tables([left, right], Some("id")).unwrap();
+
+        assert_eq!(columns.names, ["id", "name", "age", "email"]);
+        assert_eq!(columns.sides, [[true, true], [true, true], [true, false], [false, true]]);
+        // Keys that are numbers sort as numbers, so 10 comes after 3
+        assert_eq!(columns.matched.as_deref().unwrap(), [[Some(0), Some(0)], [Some(1), Some(1)], [None, Some(2)], [Some(2), None]]);
+
+        let lines = |document: &Document| document.text.split(|&b| b == b'\n').filter(|l| !l.is_empty()).map(<[u8]>::to_vec).collect::<Vec<Vec<u8>>>();
+        let cells = |row: &[&str]| row.iter().map(|c| (!c.is_empty()).then(|| c.to_string())).collect::<Vec<Option<String>>>();
+        let [left, right] = [lines(&left), lines(&right)];
+        assert_eq!(columns.cells(0, &left[0]), cells(&["1", "a\\ta", "20", ""]));
+        assert_eq!(columns.cells(0, &left[2]), cells(&["10", "c", "5", ""]));
+        assert_eq!(columns.cells(1, &right[0]), cells(&["1", "a\\ta", "", "a@x"]));
+        assert_eq!(columns.cells(1, &right[2]), cells(&["3", "z", "", "z@x"]));
+    }
+
+    // Unnamed documents are called by where they were in the stream, not where sorting put them
+    #[test]
+    fn yaml_documents_keep_their_numbers() {
+        let document = Document::parse(Structured::Yaml, b"a: 1\n---\nkind: Service\nmetadata:\n  name: web\n---\nb: 2\n").unwrap();
+        for (label, line) in [("#1 $", "a: 1"), ("#3 $", "b: 2"), ("Service/web $", "kind: Service")] {
+            let n = std::str::from_utf8(&document.text).unwrap().lines().position(|l| l.trim() == line).unwrap();
+            assert!(document.paths[n].starts_with(label), "{} is under {}", line, document.paths[n]);
+        }
+    }
+}
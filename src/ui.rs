@@ -1,52 +1,168 @@
 use std::rc::Rc;
-use ratatui::{layout::{Constraint, Direction, Flex, Layout, Rect}, style::{Color, Style}, text::Line, widgets::{Block, Borders, Paragraph}, Frame};
+use ratatui::{layout::{Constraint, Direction, Flex, Layout, Margin, Position, Rect}, style::{Color, Style}, text::Line, widgets::{Block, Borders, Clear, LineGauge, List, ListState, Paragraph}, Frame};
+use syntect::highlighting::Theme;
 
+use crate::config::Colors;
+
+// Below this many columns the sides go on top of each other instead of next to each other
+const NARROW_WIDTH: u16 = 80;
+
+#[derive(Clone, Debug, Default)]
 pub struct Ui {
-    pub boxes: Rc<[Rect]>
+    pub boxes: Rc<[Rect]>,
+    // A line above each box that stays put while it scrolls, like the header row of a table
+    pub headers: Vec<Rect>,
+    // The column down the right with the overview of the whole diff
+    pub minimap: Rect,
+    // Whether the left file is on top
+    pub vertical: bool,
 }
 
 impl Ui {
-    pub fn new(rect: Rect) -> Self {
+    /// `split` is how much of the space the left side gets, in percent. A maximized side gets all of
+    /// it and the other one gets an empty box. With `header`, each box gives up its top line for one
+    pub fn new(rect: Rect, split: u16, maximized: Option<usize>, header: bool) -> Self {
+        let [rect, minimap] = Layout::horizontal([Constraint::Fill(1), Constraint::Length(1)]).areas(rect);
+        let vertical = rect.width < NARROW_WIDTH;
+        let split = match maximized {
+            Some(0) => 100,
+            Some(_) => 0,
+            None => split
+        };
+
+        let mut boxes = Layout::default()
+            .direction(if vertical { Direction::Vertical } else { Direction::Horizontal })
+            .constraints(vec![
+                Constraint::Percentage(split),
+                Constraint::Percentage(100 - split)
+            ])
+            .flex(Flex::Start)
+            .split(rect);
+
+        let mut headers = Vec::new();
+        if header {
+            let (tops, rest): (Vec<Rect>, Vec<Rect>) = boxes.iter()
+                .map(|&b| match b.is_empty() {
+                    true => (b, b),
+                    false => {
+                        let [top, rest] = Layout::vertical([Constraint::Length(1), Constraint::Fill(1)]).areas(b);
+                        (top, rest)
+                    }
+                })
+                .unzip();
+            headers = tops;
+            boxes = rest.into();
+        }
+
         Self {
-            boxes: Layout::default()
-                    .direction(Direction::Horizontal) // Arrange items horizontally
-                    .constraints(vec![
-                        Constraint::Ratio(1, 2),
-                        Constraint::Ratio(1, 2)
-                    ])
-                    .flex(Flex::Start)
-                    .split(rect)
+            boxes,
+            headers,
+            minimap,
+            vertical
         }
     }
 
-    pub fn show_help<'a>(frame: &mut Frame<'a>, keybinds_text: &Vec<&str>) {
-        frame.render_widget(Paragraph::new("").block(generate_block(String::from("Help"))), frame.area());
-                    
-        let mut help_text = keybinds_text.clone().iter()
-            .map(|&l| {
-                if l == "[h] help" {
-                    Line::from("[h] to exit this screen")
-                } else {
-                    Line::from(l)
-                }
-            })
+    /// Whether a position is on the borders where the boxes meet
+    pub fn on_divider(&self, position: Position) -> bool {
+        let [first, second] = self.boxes[..] else {
+            return false;
+        };
+        if first.is_empty() || second.is_empty() {
+            return false;
+        }
+
+        if self.vertical {
+            (position.y + 1 == first.bottom() || position.y == second.top()) && (first.left()..first.right()).contains(&position.x)
+        } else {
+            (position.x + 1 == first.right() || position.x == second.left()) && (first.top()..first.bottom()).contains(&position.y)
+        }
+    }
+
+    /// The split that puts the divider at a position
+    pub fn split_at(&self, position: Position) -> u16 {
+        let area = self.boxes.iter().fold(Rect::default(), |area, &b| area.union(b));
+        let (offset, size) = if self.vertical {
+            (position.y.saturating_sub(area.y), area.height)
+        } else {
+            (position.x.saturating_sub(area.x), area.width)
+        };
+
+        ((offset as u32 + 1) * 100 / size.max(1) as u32) as u16
+    }
+
+    pub fn show_help<'a>(frame: &mut Frame<'a>, help: &[String], palette: &Palette) {
+        frame.render_widget(Paragraph::new("").block(generate_block(String::from("Help"), palette)), frame.area());
+
+        let mut help_text = help.iter()
+            .map(|l| Line::from(l.as_str()))
             .collect::<Vec<Line>>();
-        help_text.extend_from_slice(
-            &[
-                Line::from("[e] end of file"),
-                Line::from("[b] begining of file"),
-                Line::from("[\u{2195}] move up and down using arrow keys or mouse"),
-                Line::from("[\u{2194}] move left and right using arrow keys")
-            ]
-        );
-
-        let b = Self::center_rect(frame.area(), Constraint::Length(help_text.iter().map(|l| l.width()).max().unwrap() as u16), Constraint::Length(help_text.len() as u16 + 2));
-        frame.render_widget(
-            Paragraph::new(help_text), b
-        );
+        help_text.push(Line::from("[mouse wheel] scroll up and down"));
+        help_text.push(Line::from("[shift + mouse wheel] scroll left and right"));
+        help_text.push(Line::from("[click] focus a side, or select a change by its line numbers"));
+        help_text.push(Line::from("[drag] select text, or resize the sides from the divider"));
+
+        // Spill over into more columns when there are too many lines for the screen
+        let rows = std::cmp::max(frame.area().height.saturating_sub(2) as usize, 1);
+        let columns = help_text.chunks(rows).collect::<Vec<&[Line]>>();
+        let widths = columns.iter()
+            .map(|c| c.iter().map(|l| l.width()).max().unwrap_or(0) as u16 + 2)
+            .collect::<Vec<u16>>();
+
+        let b = Self::center_rect(frame.area(), Constraint::Length(widths.iter().sum()), Constraint::Length(std::cmp::min(help_text.len(), rows) as u16 + 2));
+        let areas = Layout::horizontal(widths.into_iter().map(Constraint::Length)).split(b.inner(Margin::new(0, 1)));
+        for (column, area) in columns.into_iter().zip(areas.iter()) {
+            frame.render_widget(
+                Paragraph::new(column.to_vec()).style(Style::default().fg(palette.foreground)), *area
+            );
+        }
+    }
+
+    // Draw a loading screen in case the files are large so the user doesn't think our program sucks (as much)
+    pub fn show_loading(frame: &mut Frame<'_>, done: usize, total: usize, palette: &Palette) {
+        let l1 = Line::from("Computing the diffs between the files...");
+        let l2 = Line::from("[q] cancel");
+
+        let b = Self::center_rect(frame.area(), Constraint::Length(l1.width() as u16), Constraint::Length(3));
+        let [text_rect, gauge_rect, hint_rect] = Layout::vertical([Constraint::Length(1); 3]).areas(b);
+
+        let gauge = LineGauge::default()
+            .ratio(if total == 0 { 1.0 } else { (done as f64 / total as f64).min(1.0) })
+            .filled_style(Style::default().fg(Color::Yellow))
+            .unfilled_style(Style::default().fg(palette.border));
+
+        frame.render_widget(generate_block(String::new(), palette), frame.area());
+        frame.render_widget(Paragraph::new(l1).centered().style(Style::default().fg(Color::Yellow)), text_rect);
+        frame.render_widget(gauge, gauge_rect);
+        frame.render_widget(Paragraph::new(l2).centered().style(Style::default().fg(palette.gutter)), hint_rect);
+    }
+
+    /// A list of the themes on top of the panes, with the one in use selected
+    pub fn show_theme_picker(frame: &mut Frame<'_>, names: &[String], selected: usize, palette: &Palette) {
+        let width = names.iter().map(|n| n.len()).max().unwrap_or(0) as u16 + 4;
+        let b = Self::center_rect(frame.area(), Constraint::Length(width), Constraint::Length(names.len() as u16 + 2));
+
+        let list = List::new(names.iter().map(|n| n.as_str()))
+            .block(generate_block(String::from("Theme"), palette))
+            .style(Style::default().fg(palette.foreground))
+            .highlight_style(Style::default().fg(palette.background).bg(palette.foreground));
+
+        frame.render_widget(Clear, b);
+        frame.render_stateful_widget(list, b, &mut ListState::default().with_selected(Some(selected)));
+    }
+
+    /// A question in a box on top of everything, with the answers under it
+    pub fn show_confirm(frame: &mut Frame<'_>, question: &str, answers: &str, palette: &Palette) {
+        let width = std::cmp::max(question.len(), answers.len()) as u16 + 4;
+        let b = Self::center_rect(frame.area(), Constraint::Length(width), Constraint::Length(4));
+        let text = vec![
+            Line::from(question).style(Style::default().fg(palette.foreground)),
+            Line::from(answers).style(Style::default().fg(palette.gutter)),
+        ];
+
+        frame.render_widget(Clear, b);
+        frame.render_widget(Paragraph::new(text).centered().block(generate_block(String::new(), palette)), b);
     }
 
-        
     pub fn center_rect(area: Rect, horizontal: Constraint, vertical: Constraint) -> Rect {
         let [area] = Layout::horizontal([horizontal])
             .flex(Flex::Center)
@@ -55,22 +171,82 @@ impl Ui {
         area
     }
 
-    pub fn get_min_width(&self) -> u16 {
-        self.boxes.iter().map(|&b| b.width).min().unwrap()
+    pub fn get_height(&self) -> usize {
+        self.boxes.iter().filter(|b| !b.is_empty()).map(|&b| b.height).min().unwrap_or(0) as usize
     }
+}
 
-    pub fn get_height(&self) -> usize {
-        self.boxes.iter().map(|&b| b.height).min().unwrap() as usize
+
+/// The colors of everything around the text, picked to go with the syntax theme
+#[derive(Clone, Copy, Debug)]
+pub struct Palette {
+    pub background: Color,
+    pub foreground: Color,
+    pub border: Color,
+    pub gutter: Color,
+    pub added: Color,
+    pub removed: Color,
+    pub changed: Color,
+    pub moved: Color,
+    pub selection: Color,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            background: Color::Rgb(0x12, 0x12, 0x12),
+            foreground: Color::Rgb(0xff, 0xff, 0xff),
+            border: Color::Rgb(0x3a, 0x3a, 0x3a),
+            gutter: Color::DarkGray,
+            added: Color::Green,
+            removed: Color::Red,
+            changed: Color::Yellow,
+            moved: Color::Cyan,
+            selection: Color::Rgb(0x44, 0x44, 0x44),
+        }
     }
 }
 
+impl Palette {
+    pub fn from_theme(theme: &Theme, colors: &Colors) -> Self {
+        let default = Self::default();
+        let rgb = |c: syntect::highlighting::Color| Color::Rgb(c.r, c.g, c.b);
+
+        let background = theme.settings.background.map(rgb).unwrap_or(default.background);
+        let foreground = theme.settings.foreground.map(rgb).unwrap_or(default.foreground);
+
+        // Light themes only work if the borders and line numbers get darker too, so mix them from the theme
+        Self {
+            background,
+            foreground,
+            border: blend(background, foreground, 0.2),
+            gutter: theme.settings.gutter_foreground.map(rgb).unwrap_or(blend(background, foreground, 0.45)),
+            added: colors.added,
+            removed: colors.removed,
+            changed: colors.changed,
+            moved: colors.moved,
+            selection: blend(background, foreground, 0.3),
+        }
+    }
+}
+
+/// Mix `amount` of `to` into `from`
+fn blend(from: Color, to: Color, amount: f64) -> Color {
+    match (from, to) {
+        (Color::Rgb(r1, g1, b1), Color::Rgb(r2, g2, b2)) => {
+            let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * amount).round() as u8;
+            Color::Rgb(mix(r1, r2), mix(g1, g2), mix(b1, b2))
+        },
+        _ => from
+    }
+}
 
-pub fn generate_block<'a>(name: String) -> Block<'a> {
+pub fn generate_block<'a>(name: String, palette: &Palette) -> Block<'a> {
     Block::default()
         .title(name)
         .title_alignment(ratatui::layout::Alignment::Center)
-        .title_style(Style::default().fg(Color::Rgb(0xff, 0xff, 0xff)))
+        .title_style(Style::default().fg(palette.foreground))
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(Color::Rgb(0x3a, 0x3a, 0x3a)))
-        .style(Style::default().bg(Color::Rgb(0x12, 0x12, 0x12)))
+        .border_style(Style::default().fg(palette.border))
+        .style(Style::default().bg(palette.background))
 }
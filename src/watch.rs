@@ -0,0 +1,80 @@
+use std::{error::Error, path::{Path, PathBuf}, sync::mpsc::{channel, Receiver}, thread, time::Duration};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+
+// Editors save in a few steps (write a temp file, rename it over, touch it...), so wait for
+// things to settle down before saying a file changed
+const SETTLE_TIME: Duration = Duration::from_millis(50);
+
+/// Tells us when either of the files changes on disk (inotify on Linux)
+pub struct Watcher {
+    // Stops watching when dropped
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    paths: [PathBuf; 2],
+}
+
+impl Watcher {
+    pub fn new(paths: [&str; 2]) -> Result<Self, Box<dyn Error>> {
+        let (tx, events) = channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+
+        // Watch the directories rather than the files. Saving by renaming a new file over the old
+        // one would leave us watching a file that isn't there anymore
+        let paths = paths.map(absolute);
+        for path in &paths {
+            let dir = path.parent().unwrap_or(Path::new("/"));
+            watcher.watch(dir, RecursiveMode::NonRecursive)
+                .map_err(|e| format!("Couldn't watch {}: {}", dir.display(), e))?;
+        }
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            paths,
+        })
+    }
+
+    /// Which of the files changed since the last time this was called
+    pub fn changed(&self) -> [bool; 2] {
+        let mut changed = self.drain();
+        if changed.contains(&true) {
+            thread::sleep(SETTLE_TIME);
+            let more = self.drain();
+            changed = [changed[0] || more[0], changed[1] || more[1]];
+        }
+
+        changed
+    }
+
+    fn drain(&self) -> [bool; 2] {
+        let mut changed = [false; 2];
+
+        for event in self.events.try_iter().flatten() {
+            if matches!(event.kind, EventKind::Access(_)) {
+                continue;
+            }
+
+            for path in event.paths.iter().map(|p| absolute(p.to_string_lossy().as_ref())) {
+                for (i, watched) in self.paths.iter().enumerate() {
+                    changed[i] |= path == *watched;
+                }
+            }
+        }
+
+        changed
+    }
+}
+
+/// The path with its directory resolved, so paths from events can be compared with it. The file
+/// itself is left alone since it may come and go
+fn absolute(path: &str) -> PathBuf {
+    let path = Path::new(path);
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    dir.canonicalize()
+        .map(|dir| dir.join(path.file_name().unwrap_or_default()))
+        .unwrap_or_else(|_| path.to_path_buf())
+}